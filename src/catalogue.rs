@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::float_curve::FloatCurve;
+use crate::part_config::load_part_configs;
+use crate::parts::{Part, PartVariant, SizeClass};
+use crate::resources::{FlowMode, Resource, Resources, register_resource};
+
+
+/// The stock parts, which are always available
+const STOCK_PARTS: &str = include_str!("../parts/stock.toml");
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogueFile {
+    #[serde(default)]
+    resource: Vec<ResourceEntry>,
+    #[serde(default)]
+    part: Vec<PartEntry>,
+}
+
+
+/// A resource that the parts of the catalogue can store and burn, on top of the stock ones
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResourceEntry {
+    name: String,
+    density: f32,
+    flow_mode: String,
+}
+
+
+/// A part as it is written in a catalogue file. Which of the optional values are needed depends on the type.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartEntry {
+    name: String,
+    ksp_name: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    mass: f32,
+    drag_area: f32,
+    drag_coefficient: f32,
+    top_node: Option<f32>,
+    bottom_node: Option<f32>,
+    #[serde(default)]
+    resources: BTreeMap<String, f32>,
+    thrust_vac: Option<f32>,
+    isp_asl: Option<f32>,
+    isp_vac: Option<f32>,
+    isp_curve: Option<Vec<Vec<f32>>>,
+    propellants: Option<BTreeMap<String, f32>>,
+    thrust_curve: Option<Vec<Vec<f32>>>,
+    thrust_max: Option<f32>,
+    velocity_curve: Option<Vec<Vec<f32>>>,
+    density_curve: Option<Vec<Vec<f32>>>,
+    area: Option<f32>,
+    output: Option<BTreeMap<String, f32>>,
+    crossfeed: Option<bool>,
+    nodes: Option<u32>,
+}
+
+
+impl PartEntry {
+    fn into_part(self) -> Result<Part, String> {
+        if self.name.trim().is_empty() {
+            return Err("the name is empty".to_string());
+        }
+        for (key, value) in [("mass", self.mass), ("drag_area", self.drag_area), ("drag_coefficient", self.drag_coefficient)] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{key} must be a positive number, not {value}"));
+            }
+        }
+
+        // Catch values that would be silently ignored, since they belong to a different type of part
+        let given = [
+            ("thrust_vac", self.thrust_vac.is_some()),
+            ("isp_asl", self.isp_asl.is_some()),
+            ("isp_vac", self.isp_vac.is_some()),
+            ("isp_curve", self.isp_curve.is_some()),
+            ("propellants", self.propellants.is_some()),
+            ("thrust_curve", self.thrust_curve.is_some()),
+            ("thrust_max", self.thrust_max.is_some()),
+            ("velocity_curve", self.velocity_curve.is_some()),
+            ("density_curve", self.density_curve.is_some()),
+            ("area", self.area.is_some()),
+            ("output", self.output.is_some()),
+            ("crossfeed", self.crossfeed.is_some()),
+            ("nodes", self.nodes.is_some()),
+        ];
+        let used: &[&str] = match self.kind.as_str() {
+            "SolidBooster" => &["thrust_vac", "isp_asl", "isp_vac", "isp_curve", "propellants", "thrust_curve"],
+            "Engine" => &["thrust_vac", "isp_asl", "isp_vac", "isp_curve", "propellants"],
+            "JetEngine" => &["thrust_max", "isp_asl", "isp_vac", "isp_curve", "propellants", "velocity_curve", "density_curve"],
+            "Intake" | "Wing" => &["area"],
+            "Generator" => &["output"],
+            "RadialDecoupler" => &["crossfeed"],
+            "MultiCoupler" => &["nodes"],
+            _ => &[],
+        };
+        if let Some((key, _)) = given.iter().find(|(key, is_given)| *is_given && !used.contains(key)) {
+            return Err(format!("{key} is not used by parts of type {}", self.kind));
+        }
+
+        let variant = match self.kind.as_str() {
+            "JetEngine" => {
+                let thrust_max = positive("thrust_max", self.thrust_max)?;
+                let isp_curve = engine_isp_curve(self.isp_curve, self.isp_asl, self.isp_vac)?;
+                let propellants = engine_propellants(self.propellants)?;
+                if !propellants.iter().any(|(resource, _)| *resource == Resource::INTAKE_AIR) {
+                    return Err("jet engines must burn IntakeAir".to_string());
+                }
+                // Without a curve, the thrust does not change with the speed or the air density
+                let curve = |key: &str, keys: Option<Vec<Vec<f32>>>| {
+                    FloatCurve::from_keys(&keys.unwrap_or(vec![vec![0.0, 1.0]])).map_err(|error| format!("{key}: {error}"))
+                };
+                let velocity_curve = curve("velocity_curve", self.velocity_curve)?;
+                let density_curve = curve("density_curve", self.density_curve)?;
+                PartVariant::JetEngine { thrust_max, isp_curve, propellants, velocity_curve, density_curve }
+            }
+            "SolidBooster" | "Engine" => {
+                let thrust_vac = positive("thrust_vac", self.thrust_vac)?;
+                let isp_curve = engine_isp_curve(self.isp_curve, self.isp_asl, self.isp_vac)?;
+                let propellants = engine_propellants(self.propellants)?;
+                if self.kind == "Engine" {
+                    PartVariant::Engine { thrust_vac, isp_curve, propellants }
+                } else {
+                    let thrust_curve = self.thrust_curve.map(|keys| FloatCurve::from_keys(&keys)
+                        .map_err(|error| format!("thrust_curve: {error}"))
+                    ).transpose()?;
+                    PartVariant::SolidBooster { thrust_vac, isp_curve, propellants, thrust_curve }
+                }
+            }
+            "Intake" => PartVariant::Intake { area: positive("area", self.area)? },
+            "Wing" => PartVariant::Wing { area: positive("area", self.area)? },
+            "Tank" => PartVariant::Tank,
+            "Generator" => PartVariant::Generator { output: resource_list("output", self.output.unwrap_or_default())? },
+            "Decoupler" => PartVariant::Decoupler,
+            "RadialDecoupler" => PartVariant::RadialDecoupler { crossfeed: self.crossfeed.unwrap_or(false) },
+            "FuelLine" => PartVariant::FuelLine,
+            "MultiCoupler" => match self.nodes {
+                Some(nodes) if nodes >= 2 => PartVariant::MultiCoupler { nodes },
+                _ => return Err("nodes must be given, and be at least 2".to_string()),
+            },
+            "Adapter" => PartVariant::Adapter,
+            "Parachute" => PartVariant::Parachute,
+            "CommandPod" => PartVariant::CommandPod,
+            kind => return Err(format!("unknown part type {kind}")),
+        };
+
+        Ok(Part {
+            ksp_name: self.ksp_name.unwrap_or_else(|| self.name.clone()),
+            name: self.name,
+            mass: self.mass,
+            drag_area: self.drag_area,
+            drag_coefficient: self.drag_coefficient,
+            resources: resource_list("resources", self.resources)?,
+            variant,
+            symmetry: 1,
+            top_node: size_class("top_node", self.top_node)?,
+            bottom_node: size_class("bottom_node", self.bottom_node)?,
+        })
+    }
+}
+
+
+fn positive(key: &str, value: Option<f32>) -> Result<f32, String> {
+    match value {
+        Some(value) if value.is_finite() && value > 0.0 => Ok(value),
+        Some(value) => Err(format!("{key} must be larger than zero, not {value}")),
+        None => Err(format!("{key} is missing")),
+    }
+}
+
+
+/// Specific impulse of an engine, either as a curve or from the values at sea level and in vacuum
+fn engine_isp_curve(keys: Option<Vec<Vec<f32>>>, isp_asl: Option<f32>, isp_vac: Option<f32>) -> Result<FloatCurve, String> {
+    let isp_curve = match (keys, isp_asl, isp_vac) {
+        (Some(keys), None, None) => FloatCurve::from_keys(&keys).map_err(|error| format!("isp_curve: {error}"))?,
+        (None, Some(isp_asl), isp_vac) => linear_isp_curve(positive("isp_vac", isp_vac)?, isp_asl),
+        (None, None, _) => return Err("isp_asl is missing".to_string()),
+        (Some(_), _, _) => return Err("isp_curve replaces isp_asl and isp_vac".to_string()),
+    };
+    if isp_curve.evaluate(0.0) <= 0.0 {
+        return Err("the specific impulse in vacuum must be larger than zero".to_string());
+    }
+    Ok(isp_curve)
+}
+
+
+fn engine_propellants(table: Option<BTreeMap<String, f32>>) -> Result<Resources, String> {
+    let propellants = resource_list("propellants", table.unwrap_or_default())?;
+    if propellants.is_empty() {
+        return Err("propellants are missing".to_string());
+    }
+    Ok(propellants)
+}
+
+
+/// Specific impulse that changes linearly between vacuum and 1 atm. At higher pressures, it keeps falling until it
+/// reaches zero.
+fn linear_isp_curve(isp_vac: f32, isp_asl: f32) -> FloatCurve {
+    let slope = isp_asl - isp_vac;
+    let mut keys = vec![[0.0, isp_vac, slope, slope], [1.0, isp_asl, slope, slope]];
+    if slope < 0.0 {
+        keys.push([isp_vac / -slope, 0.0, slope, 0.0]);
+    }
+    FloatCurve::from_keys(&keys).unwrap()
+}
+
+
+fn resource_list(key: &str, table: BTreeMap<String, f32>) -> Result<Resources, String> {
+    table.into_iter().map(|(name, amount)| {
+        let resource = Resource::from_name(&name).ok_or(format!("{key} contains the unknown resource {name}"))?;
+        if !(amount.is_finite() && amount >= 0.0) {
+            return Err(format!("{key} contains the invalid amount {amount} of {name}"));
+        }
+        Ok((resource, amount))
+    }).collect()
+}
+
+
+fn size_class(key: &str, diameter: Option<f32>) -> Result<Option<SizeClass>, String> {
+    diameter.map(|diameter| SizeClass::from_diameter(diameter)
+        .ok_or(format!("{key} has the diameter {diameter}m, which is not a standard size"))
+    ).transpose()
+}
+
+
+impl ResourceEntry {
+    fn register(self) -> Result<(), String> {
+        let flow_mode = match self.flow_mode.as_str() {
+            "NoFlow" => FlowMode::NoFlow,
+            "Stack" => FlowMode::Stack,
+            "Vessel" => FlowMode::Vessel,
+            flow_mode => return Err(format!("unknown flow mode {flow_mode}, it must be NoFlow, Stack or Vessel")),
+        };
+        register_resource(&self.name, self.density, flow_mode).map(|_| ())
+    }
+}
+
+
+/// Parses a catalogue in TOML format. Its resources are registered before its parts are read. `source` names the
+/// file in error messages.
+fn parse_catalogue(text: &str, source: &str) -> Result<Vec<Part>, String> {
+    let file: CatalogueFile = toml::from_str(text).map_err(|error| format!("{source}: {error}"))?;
+    for (i, entry) in file.resource.into_iter().enumerate() {
+        let name = entry.name.clone();
+        entry.register().map_err(|error| format!("{source}: resource {} ({name}): {error}", i + 1))?;
+    }
+    let mut parts: Vec<Part> = Vec::new();
+    for (i, entry) in file.part.into_iter().enumerate() {
+        let name = entry.name.clone();
+        let part = entry.into_part().map_err(|error| format!("{source}: part {} ({name}): {error}", i + 1))?;
+        if parts.iter().any(|other| other.name == part.name) {
+            return Err(format!("{source}: part {} ({name}): there already is a part with this name", i + 1));
+        }
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+
+pub fn stock_catalogue() -> Vec<Part> {
+    parse_catalogue(STOCK_PARTS, "parts/stock.toml").unwrap()
+}
+
+
+/// Loads additional parts from a TOML catalogue, or from KSP part configs if `path` is a directory or a .cfg file
+pub fn load_catalogue(path: &Path) -> Result<Vec<Part>, String> {
+    if path.is_dir() || path.extension().is_some_and(|extension| extension == "cfg") {
+        return load_part_configs(path);
+    }
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    parse_catalogue(&text, &path.display().to_string())
+}
+
+
+/// Adds `parts` to the catalogue. Parts with the same name as an existing one replace it.
+pub fn add_parts(catalogue: &mut Vec<Part>, parts: Vec<Part>) {
+    for part in parts {
+        match catalogue.iter_mut().find(|other| other.name == part.name) {
+            Some(other) => *other = part,
+            None => catalogue.push(part),
+        }
+    }
+}
+
+
+/// Looks up the parts of a rocket by their names
+pub fn find_parts(catalogue: &[Part], names: &[&str]) -> Result<Vec<Part>, String> {
+    names.iter().map(|&name| catalogue.iter().find(|part| part.name == name).cloned()
+        .ok_or(format!("there is no part named {name}"))
+    ).collect()
+}
@@ -0,0 +1,76 @@
+use crate::float_curve::FloatCurve;
+
+
+/// Gas envelope of a celestial body
+#[derive(Debug, Clone)]
+pub struct Atmosphere {
+    /// Altitude in m above which there is no atmosphere
+    pub height: f32,
+    /// Pressure in atm over the altitude in m
+    pub pressure: FloatCurve,
+    /// Temperature in K over the altitude in m
+    pub temperature: FloatCurve,
+    /// Density in kg/m^3 over the altitude in m. Without it, the density follows from the pressure and temperature.
+    pub density: Option<FloatCurve>,
+    /// Specific gas constant in J/(kg K)
+    pub gas_constant: f32,
+    /// Ratio of the specific heats of the gas
+    pub adiabatic_index: f32,
+    /// Whether jet engines can breathe the gas
+    pub oxygen: bool,
+}
+
+
+/// Planet or moon that a vessel launches from
+#[derive(Debug, Clone)]
+pub struct CelestialBody {
+    pub name: String,
+    /// Equatorial radius in m
+    pub radius: f32,
+    /// Standard gravitational parameter in m^3/s^2
+    pub gm: f32,
+    /// Sidereal rotation rate in rad/s
+    pub rotation_rate: f32,
+    /// Radius of the sphere of influence in m. Vessels that get further away escape the body.
+    pub soi: f32,
+    pub atmosphere: Option<Atmosphere>,
+}
+
+
+impl CelestialBody {
+    /// Returns the gravitational acceleration in m/s^2 at a distance `radius` from the center of the body
+    pub fn gravity(&self, radius: f32) -> f32 {
+        self.gm / (radius * radius)
+    }
+
+    /// Altitude in m above which there is no atmosphere, which is the surface for airless bodies
+    pub fn atmosphere_height(&self) -> f32 {
+        self.atmosphere.as_ref().map_or(0.0, |atmosphere| atmosphere.height)
+    }
+
+    /// Returns the atmospheric pressure in atm
+    pub fn pressure(&self, altitude: f32) -> f32 {
+        self.atmosphere.as_ref().map_or(0.0, |atmosphere| atmosphere.pressure.evaluate(altitude).max(0.0))
+    }
+
+    /// Returns the air density in kg/m^3, by default from the pressure and temperature by the ideal gas law
+    pub fn density(&self, altitude: f32) -> f32 {
+        self.atmosphere.as_ref().map_or(0.0, |atmosphere| match &atmosphere.density {
+            Some(density) => density.evaluate(altitude).max(0.0),
+            None => self.pressure(altitude) * 101325.0 / (atmosphere.gas_constant * atmosphere.temperature.evaluate(altitude)),
+        })
+    }
+
+    /// Returns the speed of sound in m/s, which is infinite without an atmosphere
+    pub fn speed_of_sound(&self, altitude: f32) -> f32 {
+        self.atmosphere.as_ref().map_or(f32::INFINITY, |atmosphere| {
+            (atmosphere.adiabatic_index * atmosphere.gas_constant * atmosphere.temperature.evaluate(altitude)).sqrt()
+        })
+    }
+
+    /// Whether jet engines can breathe the air at `altitude`
+    pub fn has_oxygen(&self, altitude: f32) -> bool {
+        self.atmosphere.as_ref().is_some_and(|atmosphere| atmosphere.oxygen && altitude < atmosphere.height)
+    }
+}
+
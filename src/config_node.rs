@@ -0,0 +1,149 @@
+/// A node of KSP's ConfigNode format, as used by the .cfg and .craft files. Nodes contain `key = value` pairs and
+/// further nodes, both of which can be repeated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigNode {
+    pub name: String,
+    pub values: Vec<(String, String)>,
+    pub nodes: Vec<ConfigNode>,
+}
+
+
+impl ConfigNode {
+    pub fn new(name: &str) -> ConfigNode {
+        ConfigNode { name: name.to_string(), ..ConfigNode::default() }
+    }
+
+    /// First value with the given key
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// All values with the given key, in order
+    pub fn values_named<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.values.iter().filter(move |(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// All values whose key starts with `prefix`, in order
+    pub fn values_starting_with<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        self.values.iter().filter(move |(k, _)| k.starts_with(prefix)).map(|(_, value)| value.as_str())
+    }
+
+    /// All child nodes with the given name, in order
+    pub fn nodes_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ConfigNode> {
+        self.nodes.iter().filter(move |node| node.name == name)
+    }
+
+    pub fn add_value(&mut self, key: &str, value: impl ToString) {
+        self.values.push((key.to_string(), value.to_string()));
+    }
+
+    /// Parses the value of `key` as a number. Missing values are `Ok(None)`.
+    pub fn number(&self, key: &str) -> Result<Option<f32>, String> {
+        self.value(key).map(|value| value.parse::<f32>()
+            .map_err(|_| format!("{key} = {value} is not a number"))
+        ).transpose()
+    }
+}
+
+
+/// Parses the contents of a ConfigNode file. The values and nodes at the top level of the file are collected into an
+/// unnamed root node.
+pub fn parse_config(text: &str) -> Result<ConfigNode, String> {
+    // Nodes that are still open, with the line they were opened on
+    let mut open: Vec<(ConfigNode, usize)> = vec![(ConfigNode::default(), 0)];
+    // Name of the next node, which is given on the line before its opening brace
+    let mut next_name: Option<String> = None;
+
+    for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split("//").next().unwrap();
+        // Braces can share the line with names and values, as in "MODULE { name = ModuleCommand }"
+        let mut rest = line;
+        while !rest.trim().is_empty() {
+            let split = rest.find(['{', '}']).unwrap_or(rest.len());
+            let (item, tail) = rest.split_at(split);
+            let item = item.trim();
+            if !item.is_empty() {
+                match item.split_once('=') {
+                    Some((key, value)) => {
+                        let current = &mut open.last_mut().unwrap().0;
+                        current.values.push((key.trim().to_string(), value.trim().to_string()));
+                    }
+                    None => next_name = Some(item.to_string()),
+                }
+            }
+            if tail.starts_with('{') {
+                let name = next_name.take().ok_or(format!("line {line_number}: node without a name"))?;
+                open.push((ConfigNode::new(&name), line_number));
+            } else if tail.starts_with('}') {
+                if open.len() == 1 {
+                    return Err(format!("line {line_number}: closing brace without an open node"));
+                }
+                let (node, _) = open.pop().unwrap();
+                open.last_mut().unwrap().0.nodes.push(node);
+            }
+            rest = tail.get(1..).unwrap_or("");
+        }
+    }
+
+    if open.len() > 1 {
+        let (node, line_number) = open.pop().unwrap();
+        return Err(format!("line {line_number}: node {} is never closed", node.name));
+    }
+    Ok(open.pop().unwrap().0)
+}
+
+
+/// Writes `node` in the ConfigNode format. The values and nodes of `node` itself are written at the top level, as in
+/// a file read by `parse_config`.
+pub fn write_config(node: &ConfigNode) -> String {
+    let mut text = String::new();
+    write_contents(node, 0, &mut text);
+    text
+}
+
+
+fn write_contents(node: &ConfigNode, depth: usize, text: &mut String) {
+    let indent = "\t".repeat(depth);
+    for (key, value) in &node.values {
+        text.push_str(&format!("{indent}{key} = {value}\n"));
+    }
+    for child in &node.nodes {
+        text.push_str(&format!("{indent}{}\n{indent}{{\n", child.name));
+        write_contents(child, depth + 1, text);
+        text.push_str(&format!("{indent}}}\n"));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_nodes_and_inline_braces() {
+        let text = "PART\n{\n\tname = tank // a comment\n\tMODULE { name = ModuleCommand }\n\tRESOURCE\n\t{\n\t\tamount = 90\n\t}\n}\n";
+        let root = parse_config(text).unwrap();
+        let part = root.nodes_named("PART").next().unwrap();
+        assert_eq!(part.value("name"), Some("tank"));
+        assert_eq!(part.nodes_named("MODULE").next().unwrap().value("name"), Some("ModuleCommand"));
+        assert_eq!(part.nodes_named("RESOURCE").next().unwrap().number("amount"), Ok(Some(90.0)));
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        assert!(parse_config("PART\n{\n\tname = tank\n").unwrap_err().contains("never closed"));
+        assert!(parse_config("name = tank\n}\n").unwrap_err().contains("closing brace"));
+        assert!(parse_config("{\n}\n").unwrap_err().contains("without a name"));
+    }
+
+    #[test]
+    fn write_config_round_trips() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/GameData/Squad/Parts/Engine/liquidEngineLV-T45/liquidEngineLV-T45.cfg");
+        let root = parse_config(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let written = write_config(&root);
+        assert_eq!(parse_config(&written).unwrap(), root);
+        // Writing is stable once the comments are gone
+        assert_eq!(write_config(&parse_config(&written).unwrap()), written);
+    }
+}
@@ -0,0 +1,466 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::config_node::{parse_config, write_config, ConfigNode};
+use crate::parts::Part;
+use crate::parts::PartVariant::*;
+use crate::vessel::{AttachNode, Vessel};
+
+
+/// Volume of one unit of propellant in m^3, which KSP uses for all of its tanks
+const UNIT_VOLUME: f32 = 0.005;
+
+
+/// Identifier of a part in a craft file, made up of its KSP name and a number that is unique within the craft
+fn part_id(ksp_name: &str, i: usize) -> String {
+    format!("{ksp_name}_{}", 4294967295 - i as u64)
+}
+
+
+/// KSP name of the part from its identifier
+fn ksp_name(id: &str) -> &str {
+    match id.rsplit_once('_') {
+        Some((name, number)) if number.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => id,
+    }
+}
+
+
+/// Radius of a part around its vertical axis in m
+fn part_radius(part: &Part) -> f32 {
+    let diameter = part.top_node.iter().chain(&part.bottom_node).map(|size| size.diameter()).fold(0.0, f32::max);
+    if diameter > 0.0 { diameter / 2.0 } else { 0.1 }
+}
+
+
+/// The catalogue has no part geometry, so the height of a part is estimated from the volume of its propellant and
+/// the size of its nodes. This keeps the parts from overlapping much when the craft is opened in the editor.
+fn part_height(part: &Part) -> f32 {
+    let radius = part_radius(part);
+    let units: f32 = part.resources.iter().filter(|(resource, _)| resource.density() > 0.001)
+        .map(|(_, amount)| amount).sum();
+    let base = match part.variant {
+        Decoupler | RadialDecoupler{..} | Tank => 0.2,
+        _ => 2.0 * radius,
+    };
+    base.max(units * UNIT_VOLUME / (PI * radius * radius))
+}
+
+
+fn format_vector(values: &[f32]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",")
+}
+
+
+/// One copy of a vessel part, as it is written to the craft file
+struct CraftPart {
+    /// Index of the part in the vessel
+    part: usize,
+    id: String,
+    /// Index of the craft part that this one is attached to, and the node of the parent it is attached to
+    parent: Option<(usize, AttachNode, String)>,
+    /// Position of the centre of the part
+    position: [f32; 3],
+    /// Rotation about the vertical axis in radians
+    angle: f32,
+}
+
+
+/// Activation group of every stage's engines and decoupler, counted from the launch. Engines of radial stages ignite
+/// together with the stage they are attached to, and every decoupler has its own group.
+fn stage_activations(stages: &[Vec<usize>], vessel: &Vessel) -> Vec<(usize, usize)> {
+    let mut activations: Vec<(usize, usize)> = Vec::new();
+    for s in 0..stages.len() {
+        let previous_radial = s > 0 && stages[s - 1].last().is_some_and(
+            |&i| matches!(vessel.parts[i].variant, RadialDecoupler{..})
+        );
+        let ignition = if previous_radial { activations[s - 1].0 } else { s };
+        activations.push((ignition, s + 1));
+    }
+    activations
+}
+
+
+/// Writes the rocket as a .craft file that can be opened in the VAB. Parts in symmetry are written once per copy,
+/// and the staging follows the order in which the stages are fired.
+pub fn write_craft(parts: &[Part], ship_name: &str) -> String {
+    let vessel = Vessel::from(parts);
+
+    // Place every copy of every part, from the top down
+    let mut craft_parts: Vec<CraftPart> = Vec::new();
+    let mut copies: Vec<Vec<usize>> = vec![Vec::new(); vessel.parts.len()];
+    for (i, part) in vessel.parts.iter().enumerate() {
+        let height = part_height(part);
+        let Some((p, node)) = vessel.parents[i] else {
+            copies[i].push(craft_parts.len());
+            craft_parts.push(CraftPart { part: i, id: part_id(&part.ksp_name, craft_parts.len()), parent: None,
+                position: [0.0, -height / 2.0, 0.0], angle: 0.0 });
+            continue;
+        };
+        let parent = &vessel.parts[p];
+        let count = (part.symmetry / parent.symmetry).max(1);
+        for pc in copies[p].clone() {
+            for m in 0..count {
+                let [x, y, z] = craft_parts[pc].position;
+                let angle = craft_parts[pc].angle + 2.0 * PI * m as f32 / count as f32;
+                let (position, parent_node) = match node {
+                    AttachNode::Stack if count == 1 => {
+                        ([x, y - part_height(parent) / 2.0 - height / 2.0, z], "bottom".to_string())
+                    }
+                    AttachNode::Stack => {
+                        // The copies below a multi-coupler are spread evenly around its axis
+                        let offset = part_radius(part) / (PI / count as f32).sin();
+                        let y = y - part_height(parent) / 2.0 - height / 2.0;
+                        ([x + offset * angle.cos(), y, z + offset * angle.sin()], format!("bottom{:02}", m + 1))
+                    }
+                    AttachNode::Radial => {
+                        let offset = part_radius(parent) + part_radius(part);
+                        ([x + offset * angle.cos(), y, z + offset * angle.sin()], "srfAttach".to_string())
+                    }
+                };
+                copies[i].push(craft_parts.len());
+                craft_parts.push(CraftPart { part: i, id: part_id(&part.ksp_name, craft_parts.len()),
+                    parent: Some((pc, node, parent_node)), position, angle });
+            }
+        }
+    }
+
+    // Lift the rocket so that its lowest part stands just above the ground
+    let lowest = craft_parts.iter()
+        .map(|c| c.position[1] - part_height(&vessel.parts[c.part]) / 2.0)
+        .fold(0.0, f32::min);
+    for c in &mut craft_parts {
+        c.position[1] += 1.0 - lowest;
+    }
+
+    let stages = vessel.stage_indices();
+    let activations = stage_activations(&stages, &vessel);
+    let mut stage_of = vec![0; vessel.parts.len()];
+    for (s, stage) in stages.iter().enumerate() {
+        for &i in stage {
+            stage_of[i] = s;
+        }
+    }
+    // KSP counts the stages down to 0, with parachutes in the last one
+    let last = stages.len();
+    let mut stage_counts = vec![0; last + 1];
+
+    let mut root = ConfigNode::new("");
+    root.add_value("ship", ship_name);
+    root.add_value("version", "1.12.5");
+    root.add_value("description", "Designed by ksp_optimizer");
+    root.add_value("type", "VAB");
+    let width = craft_parts.iter().map(|c| c.position[0].abs().max(c.position[2].abs()) + part_radius(&vessel.parts[c.part]))
+        .fold(0.0, f32::max);
+    let height = craft_parts.iter().map(|c| c.position[1] + part_height(&vessel.parts[c.part]) / 2.0)
+        .fold(0.0, f32::max) - 1.0;
+    root.add_value("size", format_vector(&[2.0 * width, height, 2.0 * width]));
+    root.add_value("rot", "0,0,0,1");
+    root.add_value("missionFlag", "Squad/Flags/default");
+    root.add_value("vesselType", "Ship");
+
+    for (c, craft_part) in craft_parts.iter().enumerate() {
+        let part = &vessel.parts[craft_part.part];
+        let s = stage_of[craft_part.part];
+        let (ignition, decouple) = activations[s];
+        let activation = match part.variant {
+            SolidBooster{..} | Engine{..} | JetEngine{..} => Some(ignition),
+            Decoupler | RadialDecoupler{..} => Some(decouple),
+            Parachute => Some(last),
+            _ => None,
+        };
+        let decouple_stage = if s + 1 < last { last - decouple } else { 0 };
+        let [x, y, z] = craft_part.position;
+        let rotation = format_vector(&[0.0, (craft_part.angle / 2.0).sin(), 0.0, (craft_part.angle / 2.0).cos()]);
+
+        let mut node = ConfigNode::new("PART");
+        node.add_value("part", &craft_part.id);
+        node.add_value("partName", "Part");
+        node.add_value("persistentId", 1000000 + c);
+        node.add_value("pos", format_vector(&[x, y, z]));
+        node.add_value("attPos", "0,0,0");
+        node.add_value("attPos0", format_vector(&[x, y, z]));
+        node.add_value("rot", &rotation);
+        node.add_value("attRot", "0,0,0,1");
+        node.add_value("attRot0", &rotation);
+        node.add_value("mir", "1,1,1");
+        node.add_value("symMethod", "Radial");
+        match activation {
+            Some(activation) => {
+                let istg = last - activation;
+                node.add_value("istg", istg);
+                node.add_value("resPri", 0);
+                node.add_value("dstg", decouple_stage);
+                node.add_value("sidx", stage_counts[istg]);
+                node.add_value("sqor", istg);
+                stage_counts[istg] += 1;
+            }
+            None => {
+                node.add_value("istg", decouple_stage);
+                node.add_value("resPri", 0);
+                node.add_value("dstg", decouple_stage);
+                node.add_value("sidx", -1);
+                node.add_value("sqor", -1);
+            }
+        }
+        node.add_value("sepI", decouple_stage);
+        let surface = matches!(craft_part.parent, Some((_, AttachNode::Radial, _)));
+        node.add_value("attm", if surface { 1 } else { 0 });
+
+        for child in craft_parts.iter().filter(|child| child.parent.as_ref().is_some_and(|(p, _, _)| *p == c)) {
+            node.add_value("link", &child.id);
+        }
+        for child in craft_parts.iter().filter(|child| child.parent.as_ref().is_some_and(|(p, _, _)| *p == c)) {
+            if let Some((_, AttachNode::Stack, parent_node)) = &child.parent {
+                node.add_value("attN", format!("{parent_node},{}", child.id));
+            }
+        }
+        match &craft_part.parent {
+            Some((p, AttachNode::Stack, _)) => node.add_value("attN", format!("top,{}", craft_parts[*p].id)),
+            Some((p, AttachNode::Radial, _)) => node.add_value("srfN", format!("srfAttach,{}", craft_parts[*p].id)),
+            None => {}
+        }
+        for &other in &copies[craft_part.part] {
+            if other != c {
+                node.add_value("sym", &craft_parts[other].id);
+            }
+        }
+
+        for (resource, amount) in &part.resources {
+            let mut resource_node = ConfigNode::new("RESOURCE");
+            resource_node.add_value("name", resource.name());
+            resource_node.add_value("amount", amount);
+            resource_node.add_value("maxAmount", amount);
+            resource_node.add_value("flowState", "True");
+            resource_node.add_value("isTweakable", "True");
+            resource_node.add_value("hideFlow", "False");
+            resource_node.add_value("isVisible", "True");
+            resource_node.add_value("flowMode", "Both");
+            node.nodes.push(resource_node);
+        }
+        root.nodes.push(node);
+    }
+    write_config(&root)
+}
+
+
+pub fn save_craft(path: &Path, parts: &[Part], ship_name: &str) -> Result<(), String> {
+    fs::write(path, write_craft(parts, ship_name)).map_err(|error| format!("{}: {error}", path.display()))
+}
+
+
+/// The parts of a craft file and how they are connected. Stack connections are stored in both directions, since
+/// KSP builds the tree from the root part, which need not be at the top of the rocket.
+struct CraftGraph {
+    parts: Vec<Part>,
+    above: Vec<Option<usize>>,
+    below: Vec<Vec<usize>>,
+    surface: Vec<Vec<usize>>,
+    /// False for the copies of a symmetric part except the first, which are described by its symmetry
+    kept: Vec<bool>,
+}
+
+
+impl CraftGraph {
+    fn parse(root: &ConfigNode, catalogue: &[Part]) -> Result<CraftGraph, String> {
+        let nodes: Vec<&ConfigNode> = root.nodes_named("PART").collect();
+        if nodes.is_empty() {
+            return Err("the craft has no parts".to_string());
+        }
+        let mut ids = Vec::new();
+        let mut parts = Vec::new();
+        for node in &nodes {
+            let id = node.value("part").ok_or("a part has no name")?;
+            let name = ksp_name(id);
+            let part = catalogue.iter().find(|part| part.ksp_name == name || part.name == name)
+                .ok_or(format!("the part {name} is not in the catalogue"))?;
+            ids.push(id.to_string());
+            parts.push(part.clone());
+        }
+        let find = |id: &str| ids.iter().position(|other| other == id).ok_or(format!("there is no part {id}"));
+
+        let n = nodes.len();
+        let mut graph = CraftGraph { parts, above: vec![None; n], below: vec![Vec::new(); n],
+            surface: vec![Vec::new(); n], kept: vec![true; n] };
+        for (i, node) in nodes.iter().enumerate() {
+            for link in node.values_named("link") {
+                let child = find(link)?;
+                let child_node = nodes[child];
+                let surface = child_node.value("attm") == Some("1") || child_node.value("srfN").is_some_and(
+                    |value| value.split_once(',').is_some_and(|(_, parent)| parent.starts_with(&ids[i])));
+                // Newer versions append the node positions to the identifier in attN
+                let parent_node = node.values_named("attN").filter_map(|value| value.split_once(','))
+                    .find(|(_, id)| *id == link || id.starts_with(&format!("{link}_")))
+                    .map(|(name, _)| name);
+                if surface {
+                    graph.surface[i].push(child);
+                } else if parent_node.is_some_and(|name| name.starts_with("top")) {
+                    graph.above[i] = Some(child);
+                    graph.below[child].push(i);
+                } else {
+                    graph.below[i].push(child);
+                    graph.above[child] = Some(i);
+                }
+            }
+        }
+
+        // Only the first copy of a symmetric part is kept, with the number of copies as its symmetry
+        for (i, node) in nodes.iter().enumerate() {
+            if !graph.kept[i] {
+                continue;
+            }
+            let mut symmetry = 1;
+            for id in node.values_named("sym") {
+                graph.kept[find(id)?] = false;
+                symmetry += 1;
+            }
+            graph.parts[i].symmetry = symmetry;
+        }
+        Ok(graph)
+    }
+
+    fn kept_below(&self, i: usize) -> Vec<usize> {
+        self.below[i].iter().copied().filter(|&b| self.kept[b]).collect()
+    }
+
+    /// Describes the parts in and below part `i`, to compare the stacks below a multi-coupler
+    fn signature(&self, i: usize) -> String {
+        let surface: Vec<String> = self.surface[i].iter().map(|&s| self.signature(s)).collect();
+        let below: Vec<String> = self.below[i].iter().map(|&b| self.signature(b)).collect();
+        format!("{}({})[{}]", self.parts[i].name, surface.join(","), below.join(","))
+    }
+
+    fn drop_part(&mut self, i: usize) {
+        self.kept[i] = false;
+        for s in self.surface[i].clone() {
+            self.drop_part(s);
+        }
+        for b in self.below[i].clone() {
+            self.drop_part(b);
+        }
+    }
+
+    /// Lists the stack that starts at part `top` from the top down, followed by the radial stages on its last stage
+    fn list_stack(&mut self, top: usize, list: &mut Vec<usize>) -> Result<(), String> {
+        let mut radial: Vec<usize> = Vec::new();
+        let mut current = Some(top);
+        while let Some(i) = current {
+            if matches!(self.parts[i].variant, Decoupler) && !radial.is_empty() {
+                return Err(format!("{} is mounted on a stage that is not the last one of its stack, which is not \
+                    supported", self.parts[radial[0]].name));
+            }
+            list.push(i);
+            for s in self.surface[i].clone() {
+                if !self.kept[s] {
+                    continue;
+                }
+                if let RadialDecoupler{..} = self.parts[s].variant {
+                    radial.push(s);
+                } else {
+                    list.push(s);
+                }
+            }
+
+            let below = self.kept_below(i);
+            if below.len() > 1 {
+                if self.parts[i].bottom_nodes() as usize != below.len() {
+                    return Err(format!("{} has {} parts stacked below it", self.parts[i].name, below.len()));
+                }
+                // The stacks below a multi-coupler are placed in symmetry, so only the first one is kept
+                let signature = self.signature(below[0]);
+                for &other in &below[1..] {
+                    if self.signature(other) != signature {
+                        return Err(format!("the stacks below {} are not identical", self.parts[i].name));
+                    }
+                    self.drop_part(other);
+                }
+            }
+            current = below.first().copied();
+        }
+
+        for decoupler in radial {
+            list.push(decoupler);
+            let mounted: Vec<usize> = self.surface[decoupler].iter().copied().filter(|&s| self.kept[s]).collect();
+            let [mut top] = mounted[..] else {
+                return Err(format!("{} must have exactly one part mounted on it", self.parts[decoupler].name));
+            };
+            while let Some(above) = self.above[top] {
+                top = above;
+            }
+            self.list_stack(top, list)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Reads a .craft file into the list form of a rocket, with the parts taken from the catalogue by their KSP name.
+/// Symmetric copies are merged into one part. Radial decouplers are only supported on the last stage of a stack,
+/// which is the only place where the list form can represent them.
+pub fn load_craft(path: &Path, catalogue: &[Part]) -> Result<Vec<Part>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let root = parse_config(&text).map_err(|error| format!("{}: {error}", path.display()))?;
+    let mut graph = CraftGraph::parse(&root, catalogue).map_err(|error| format!("{}: {error}", path.display()))?;
+
+    let mut top = 0;
+    while let Some(above) = graph.above[top] {
+        top = above;
+    }
+    let mut list = Vec::new();
+    graph.list_stack(top, &mut list).map_err(|error| format!("{}: {error}", path.display()))?;
+    if let Some(i) = (0..graph.parts.len()).find(|&i| graph.kept[i] && !list.contains(&i)) {
+        return Err(format!("{}: {} is attached in a way that is not supported", path.display(), graph.parts[i].name));
+    }
+
+    // The list form goes from the bottom up
+    Ok(list.iter().rev().map(|&i| graph.parts[i].clone()).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalogue::stock_catalogue;
+
+    fn fixture_text() -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/Ships/VAB/Hammer Duo.craft");
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn load_text(text: &str, file_name: &str) -> Result<Vec<Part>, String> {
+        let path = std::env::temp_dir().join(format!("{}-{file_name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let parts = load_craft(&path, &stock_catalogue());
+        fs::remove_file(&path).unwrap();
+        parts
+    }
+
+    fn describe(parts: &[Part]) -> Vec<(&str, u32)> {
+        parts.iter().map(|part| (part.ksp_name.as_str(), part.symmetry)).collect()
+    }
+
+    #[test]
+    fn round_trips_the_fixture_craft() {
+        let parts = load_text(&fixture_text(), "loaded.craft").unwrap();
+        assert_eq!(describe(&parts), [
+            ("solidBooster.sm.v2", 2), ("radialDecoupler", 2), ("solidBooster1-1", 1), ("Decoupler.1", 1),
+            ("liquidEngine2.v2", 1), ("fuelTank", 1), ("mk1pod.v2", 1), ("parachuteSingle", 1),
+        ]);
+
+        let reloaded = load_text(&write_craft(&parts, "Hammer Duo"), "written.craft").unwrap();
+        assert_eq!(describe(&reloaded), describe(&parts));
+    }
+
+    #[test]
+    fn rejects_radial_decouplers_on_inner_stages() {
+        // Moves the radial boosters from the lower stage to the fuel tank of the upper one
+        let text = fixture_text()
+            .replace("\tlink = radialDecoupler_4294684402\n\tlink = radialDecoupler_4294684282\n", "")
+            .replace("\tlink = liquidEngine2.v2_4294697034\n", "\tlink = liquidEngine2.v2_4294697034\n\
+                \tlink = radialDecoupler_4294684402\n\tlink = radialDecoupler_4294684282\n")
+            .replace("srfN = srfAttach,solidBooster1-1_4294688752", "srfN = srfAttach,fuelTank_4294699514");
+        let error = load_text(&text, "inner.craft").unwrap_err();
+        assert!(error.contains("TT-38K is mounted on a stage that is not the last one"), "{error}");
+    }
+}
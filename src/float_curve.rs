@@ -0,0 +1,121 @@
+/// Key of a `FloatCurve`, with the slope of the curve on either side of it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CurveKey {
+    pub time: f32,
+    pub value: f32,
+    pub in_tangent: f32,
+    pub out_tangent: f32,
+}
+
+
+/// Curve through a list of keys, interpolated with cubic Hermite splines like KSP's FloatCurve. Before the first and
+/// after the last key, the curve keeps the value of that key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatCurve {
+    keys: Vec<CurveKey>,
+}
+
+
+impl FloatCurve {
+    /// Builds a curve from keys in the format of KSP's configs, which are either `time value` or
+    /// `time value in_tangent out_tangent`. Keys without tangents get smooth ones, with the slope between their
+    /// neighbours.
+    pub fn from_keys<K: AsRef<[f32]>>(keys: &[K]) -> Result<FloatCurve, String> {
+        if keys.is_empty() {
+            return Err("the curve has no keys".to_string());
+        }
+        let mut sorted: Vec<&[f32]> = keys.iter().map(|key| key.as_ref()).collect();
+        sorted.sort_by(|a, b| a.first().unwrap_or(&0.0).total_cmp(b.first().unwrap_or(&0.0)));
+        for key in &sorted {
+            if !(key.len() == 2 || key.len() == 4) || key.iter().any(|number| !number.is_finite()) {
+                return Err(format!("the key {key:?} must consist of a time and a value, and optionally two tangents"));
+            }
+        }
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[0][0] == pair[1][0]) {
+            return Err(format!("there are two keys at {}", pair[0][0]));
+        }
+
+        let slope = |a: &[f32], b: &[f32]| (b[1] - a[1]) / (b[0] - a[0]);
+        let keys = (0..sorted.len()).map(|i| {
+            let key = sorted[i];
+            let (in_tangent, out_tangent) = if key.len() == 4 {
+                (key[2], key[3])
+            } else {
+                let previous = sorted[i.saturating_sub(1)];
+                let next = sorted[(i + 1).min(sorted.len() - 1)];
+                let tangent = if previous[0] < next[0] { slope(previous, next) } else { 0.0 };
+                (tangent, tangent)
+            };
+            CurveKey { time: key[0], value: key[1], in_tangent, out_tangent }
+        }).collect();
+        Ok(FloatCurve { keys })
+    }
+
+    pub fn evaluate(&self, time: f32) -> f32 {
+        let first = self.keys[0];
+        let last = self.keys[self.keys.len() - 1];
+        if time <= first.time {
+            return first.value;
+        }
+        if time >= last.time {
+            return last.value;
+        }
+        let i = self.keys.iter().position(|key| key.time > time).unwrap();
+        let (a, b) = (self.keys[i - 1], self.keys[i]);
+        let dt = b.time - a.time;
+        let s = (time - a.time) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        (2.0 * s3 - 3.0 * s2 + 1.0) * a.value
+            + (s3 - 2.0 * s2 + s) * dt * a.out_tangent
+            + (-2.0 * s3 + 3.0 * s2) * b.value
+            + (s3 - s2) * dt * b.in_tangent
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn reproduces_a_cubic_from_its_tangents() {
+        // KSP scales the tangents by the spacing of the keys, which makes the spline exact for t^3
+        let curve = FloatCurve::from_keys(&[[0.0, 0.0, 0.0, 0.0], [1.0, 1.0, 3.0, 3.0], [3.0, 27.0, 27.0, 27.0]]).unwrap();
+        for time in [0.25, 0.5, 1.5, 2.0, 2.75] {
+            assert_close(curve.evaluate(time), time * time * time);
+        }
+    }
+
+    #[test]
+    fn smooths_keys_without_tangents() {
+        let line = FloatCurve::from_keys(&[[3.0, 3.0], [0.0, 0.0], [1.0, 1.0]]).unwrap();
+        assert_close(line.evaluate(0.5), 0.5);
+        assert_close(line.evaluate(2.0), 2.0);
+
+        // The tangents are the slopes between the neighbours, and the ones of the end keys towards their neighbour
+        let curve = FloatCurve::from_keys(&[[0.0, 0.0], [1.0, 1.0], [2.0, 4.0]]).unwrap();
+        assert_close(curve.evaluate(0.5), 0.375);
+        assert_close(curve.evaluate(1.5), 2.375);
+    }
+
+    #[test]
+    fn keeps_the_end_values_outside_the_keys() {
+        let curve = FloatCurve::from_keys(&[vec![0.0, 320.0], vec![1.0, 250.0], vec![6.0, 0.001]]).unwrap();
+        assert_eq!(curve.evaluate(-1.0), 320.0);
+        assert_eq!(curve.evaluate(1.0), 250.0);
+        assert_eq!(curve.evaluate(10.0), 0.001);
+        assert_eq!(FloatCurve::from_keys(&[[5.0, 2.0]]).unwrap().evaluate(0.0), 2.0);
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        assert!(FloatCurve::from_keys::<[f32; 2]>(&[]).unwrap_err().contains("no keys"));
+        assert!(FloatCurve::from_keys(&[[1.0, 2.0], [1.0, 3.0]]).unwrap_err().contains("two keys at 1"));
+        assert!(FloatCurve::from_keys(&[vec![0.0, 1.0, 2.0]]).unwrap_err().contains("must consist of"));
+        assert!(FloatCurve::from_keys(&[[0.0, f32::NAN]]).unwrap_err().contains("must consist of"));
+    }
+}
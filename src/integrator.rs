@@ -1,4 +1,3 @@
-use std::f32::EPSILON;
 use crate::vector::Vector;
 
 
@@ -8,9 +7,11 @@ pub struct IntegratorSolInfo {
 }
 
 
-pub fn rk45<const N: usize, F>(f: F, y0: Vector<N>, t0: f32, tend: f32, atol: Vector<N>, rtol: f32) -> (Vector<N>, IntegratorSolInfo)
+/// Integrates `f` from `t0` to `tend`. `observe` is called with the time and state after every accepted step.
+pub fn rk45<const N: usize, F, O>(f: F, y0: Vector<N>, t0: f32, tend: f32, atol: Vector<N>, rtol: f32, mut observe: O) -> (Vector<N>, IntegratorSolInfo)
 where
-    F: Fn(f32, Vector<N>) -> Vector<N>
+    F: Fn(f32, Vector<N>) -> Vector<N>,
+    O: FnMut(f32, &Vector<N>),
 {
     // That this integrator uses half-precision is unusual, but should double performance, since we will not
     // even be running close to that precision range.
@@ -38,15 +39,18 @@ where
         // Apply RK5
         let y2 = y + (16.0/135.0) * k1 + (6656.0/12825.0) * k3 + (28561.0/56430.0) * k4 - (9.0/50.0) * k5 + (2.0/55.0) * k6;
 
-        let error =  ((y2 - y1).abs() + EPSILON) / (atol + rtol*y2.abs());
+        let error =  ((y2 - y1).abs() + f32::EPSILON) / (atol + rtol*y2.abs());
         let error = (error * error).sum();
         // println!("error: {}, f({}): {:?}, y: {:?}", error, t+h, f(t+h, y), y2);
         if error <= 1.0 {
             t += h;
             y = y1;
+            observe(t, &y);
         }
-        // Optimize step size to achieve desired accuracy
-        h *= 0.84 * error.powf(-0.125);
+        // Optimize step size to achieve desired accuracy. The change per step is limited, so that a non-finite
+        // error (e.g. from overflowing a stiff derivative) shrinks the step instead of poisoning it.
+        let error = if error.is_nan() { f32::INFINITY } else { error };
+        h *= (0.84 * error.powf(-0.125)).clamp(0.1, 4.0);
 
         step_count += 1;
     }
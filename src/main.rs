@@ -5,17 +5,34 @@ extern crate test;
 mod parts;
 mod rocket_analysis;
 mod vector;
-mod kerbin;
+mod celestial_body;
+mod planetary_system;
 mod integrator;
+mod orbit;
+mod propulsion;
+mod resources;
+mod vessel;
+mod catalogue;
+mod config_node;
+mod part_config;
+mod craft;
+mod float_curve;
 
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::process;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use rand::prelude::*;
 
-use rocket_analysis::{analyze_rocket, print_rocket_info, RocketInfo};
+use rocket_analysis::{analyze_rocket, print_rocket_info, RocketInfo, AscentMode, PitchProgram, TakeoffProgram, LaunchSite};
+use rocket_analysis::{KSC_ALTITUDE, KSC_LATITUDE};
 use parts::*;
 use parts::PartVariant::*;
+use vessel::Vessel;
+use catalogue::{add_parts, find_parts, load_catalogue, stock_catalogue};
+use craft::{load_craft, save_craft};
+use planetary_system::{add_bodies, find_body, load_system, stock_bodies};
 
 
 #[derive(Parser, Debug)]
@@ -24,30 +41,146 @@ struct Args {
     /// Number of rockets to generate
     #[arg(short, long, default_value_t = 10000)]
     count: usize,
+
+    /// Ascent trajectory that is flown by every rocket
+    #[arg(short, long, value_enum, default_value_t = Ascent::Vertical)]
+    ascent: Ascent,
+
+    /// What the optimizer is trying to achieve
+    #[arg(short, long, value_enum, default_value_t = Objective::Altitude)]
+    objective: Objective,
+
+    /// Body that the rocket launches from, like Kerbin, Mun, Minmus, Duna, Eve or Laythe
+    #[arg(short, long, default_value = "Kerbin")]
+    body: String,
+
+    /// Additional celestial bodies, as a TOML file in the format of bodies/stock.toml. Bodies with the name of a stock
+    /// body replace it.
+    #[arg(long)]
+    bodies: Option<PathBuf>,
+
+    /// Latitude of the launch site in degrees, north of the equator
+    #[arg(long, default_value_t = KSC_LATITUDE, allow_negative_numbers = true)]
+    latitude: f32,
+
+    /// Height of the launch site above the surface in m
+    #[arg(long, default_value_t = KSC_ALTITUDE, allow_negative_numbers = true)]
+    launch_altitude: f32,
+
+    /// Inclination of the target orbit in degrees. Orbits above 90 degrees are retrograde.
+    #[arg(short, long, default_value_t = 0.0)]
+    inclination: f32,
+
+    /// Altitude in m at which the gravity turn starts
+    #[arg(long, default_value_t = PitchProgram::default().start_altitude)]
+    turn_start: f32,
+
+    /// Altitude in m at which the gravity turn ends, or at which a spaceplane has pitched down to the horizon
+    #[arg(long, default_value_t = PitchProgram::default().end_altitude)]
+    turn_end: f32,
+
+    /// Pitch above the horizon in degrees at the end of the gravity turn
+    #[arg(long, default_value_t = PitchProgram::default().end_pitch)]
+    turn_end_pitch: f32,
+
+    /// Speed in m/s at which a spaceplane pitches up to leave the runway
+    #[arg(long, default_value_t = TakeoffProgram::default().rotation_speed)]
+    rotation_speed: f32,
+
+    /// Pitch above the horizon in degrees at which a spaceplane climbs after leaving the runway
+    #[arg(long, default_value_t = TakeoffProgram::default().climb_pitch)]
+    climb_pitch: f32,
+
+    /// Additional parts, either as a TOML file in the format of parts/stock.toml, or as KSP part configs (a .cfg
+    /// file, or a directory like GameData that is searched for them). Parts with the name of a stock part replace it.
+    #[arg(long)]
+    parts: Option<PathBuf>,
+
+    /// KSP .craft file with the starting rocket, instead of the default rocket or spaceplane. Its parts are looked
+    /// up in the catalogue by their KSP name.
+    #[arg(long)]
+    craft: Option<PathBuf>,
+
+    /// Writes the best rocket to this .craft file, which can be opened in the VAB
+    #[arg(long)]
+    save_craft: Option<PathBuf>,
+}
+
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Ascent {
+    Vertical,
+    GravityTurn,
+    /// Spaceplane that takes off from the runway
+    HorizontalTakeoff,
 }
 
 
-fn permute_parts(base_parts: &[Part]) -> Vec<Part> {
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Objective {
+    /// Maximize the altitude at burnout of the final stage
+    Altitude,
+    /// Reach a stable orbit above the atmosphere with as much delta-v left over as possible
+    Orbit,
+}
+
+
+impl Args {
+    fn ascent_mode(&self) -> AscentMode {
+        match self.ascent {
+            Ascent::Vertical => AscentMode::Vertical,
+            Ascent::GravityTurn => AscentMode::GravityTurn(PitchProgram {
+                start_altitude: self.turn_start,
+                end_altitude: self.turn_end,
+                end_pitch: self.turn_end_pitch,
+                ..PitchProgram::default()
+            }),
+            Ascent::HorizontalTakeoff => AscentMode::HorizontalTakeoff(TakeoffProgram {
+                rotation_speed: self.rotation_speed,
+                climb_pitch: self.climb_pitch,
+                end_altitude: self.turn_end,
+            }),
+        }
+    }
+}
+
+
+/// Randomly changes the rocket, retrying until the result has no incompatible stack joins
+fn permute_parts(base_parts: &[Part], catalogue: &[Part]) -> Option<Vec<Part>> {
+    // Gives up if the base rocket cannot be fixed by a single change
+    (0..1000)
+        .map(|_| mutate_parts(base_parts, catalogue))
+        .find(|parts| Vessel::from(parts.as_slice()).stack_errors().is_empty())
+}
+
+
+fn mutate_parts(base_parts: &[Part], catalogue: &[Part]) -> Vec<Part> {
     let mut parts = base_parts.to_vec();
 
     loop {
         let r = random::<f32>() % 1.0;
         if r > 0.85 {
             // Add new
-            let part_type = random::<usize>() % PART_CATALOGUE.len(); 
+            let part_type = random::<usize>() % catalogue.len(); 
             let part_i = random::<usize>() % (parts.len() + 1); 
-            parts.insert(part_i, PART_CATALOGUE[part_type]);
+            parts.insert(part_i, catalogue[part_type].clone());
+        } else if r > 0.75 {
+            // Change the symmetry of a radial stage
+            let radial: Vec<usize> = (0..parts.len()).filter(|&i| matches!(parts[i].variant, RadialDecoupler{..})).collect();
+            if let Some(&part_i) = radial.choose(&mut thread_rng()) {
+                parts[part_i].symmetry = *SYMMETRY_COUNTS.choose(&mut thread_rng()).unwrap();
+            }
         } else if r > 0.2 {
             // Replace
-            if parts.len() > 0 {
+            if !parts.is_empty() {
                 let part_i = random::<usize>() % parts.len(); 
                 parts.remove(part_i);
-                let part_type = random::<usize>() % PART_CATALOGUE.len();
-                parts.insert(part_i, PART_CATALOGUE[part_type]);
+                let part_type = random::<usize>() % catalogue.len();
+                parts.insert(part_i, catalogue[part_type].clone());
             }
         } else {
             // Remove
-            if parts.len() > 0 {
+            if !parts.is_empty() {
                 let part_i = random::<usize>() % parts.len(); 
                 parts.remove(part_i);
             }
@@ -57,11 +190,29 @@ fn permute_parts(base_parts: &[Part]) -> Vec<Part> {
             break;
         }
     }
+    apply_symmetry(&mut parts);
     parts
 }
 
 
-fn check_validity(rocket_info: &RocketInfo) -> bool {
+/// Radial stages are placed in symmetry as a whole, so all of their parts take on the symmetry of the radial decoupler.
+/// The parts in the stack are only symmetric below a multi-coupler, which multiplies the number of copies.
+fn apply_symmetry(parts: &mut [Part]) {
+    // Going from the top down, every stage starts with its decoupler
+    let mut symmetry = 1;
+    for part in parts.iter_mut().rev() {
+        match part.variant {
+            RadialDecoupler{..} => symmetry = part.symmetry,
+            Decoupler => symmetry = 1,
+            _ => {}
+        }
+        part.symmetry = symmetry;
+        symmetry *= part.bottom_nodes();
+    }
+}
+
+
+fn check_validity(rocket_info: &RocketInfo, ascent: &AscentMode) -> bool {
     let final_stage = &rocket_info.stages[rocket_info.stages.len() - 1];
     let contains_command_pod = final_stage.iter().any(
         |p| matches!(p.variant, CommandPod)
@@ -69,6 +220,10 @@ fn check_validity(rocket_info: &RocketInfo) -> bool {
     let has_parachute = final_stage.iter().any(
         |p| matches!(p.variant, Parachute)
     );
+    // Spaceplanes are carried by their wings, so they only need enough thrust to get going on the runway, which the
+    // TWR alone decides
+    let takeoff = matches!(ascent, AscentMode::HorizontalTakeoff(_));
+    let min_twr = if takeoff { 0.2 } else { 1.5 };
     let second_stage_twr = if rocket_info.stage_info.len() > 1 {
         rocket_info.stage_info[1].twr > 0.5
     } else {
@@ -78,15 +233,24 @@ fn check_validity(rocket_info: &RocketInfo) -> bool {
     rocket_info.launch_mass < 50.0 &&
     contains_command_pod &&
     has_parachute &&
-    rocket_info.stage_info[0].twr > 1.5 && second_stage_twr
+    rocket_info.stage_info[0].twr > min_twr && second_stage_twr &&
+    (takeoff || rocket_info.stage_info[0].stalled_engines.is_empty())
 }
 
 
 /// Returns "true" if `new_rocket` is better than `base_rocket`
-fn compare_rockets(old_rocket: &RocketInfo, new_rocket: &RocketInfo) -> bool {
-    if !check_validity(new_rocket) {
+fn compare_rockets(old_rocket: &RocketInfo, new_rocket: &RocketInfo, ascent: &AscentMode, objective: Objective) -> bool {
+    if !check_validity(new_rocket, ascent) {
         return false;
     }
+    match objective {
+        Objective::Altitude => compare_altitude(old_rocket, new_rocket),
+        Objective::Orbit => compare_orbit(old_rocket, new_rocket),
+    }
+}
+
+
+fn compare_altitude(old_rocket: &RocketInfo, new_rocket: &RocketInfo) -> bool {
     //if new_rocket.delta_v < old_rocket.delta_v - 0.1 {
     if new_rocket.final_altitude < old_rocket.final_altitude {
         // We are worse in delta-V
@@ -98,21 +262,45 @@ fn compare_rockets(old_rocket: &RocketInfo, new_rocket: &RocketInfo) -> bool {
         return false;
     } 
     // We are better.
-    return true;
+    true
 }
 
 
-fn optimize_rocket(starting_rocket: &[Part], iterations: usize) {
+fn compare_orbit(old_rocket: &RocketInfo, new_rocket: &RocketInfo) -> bool {
+    if new_rocket.reached_orbit != old_rocket.reached_orbit {
+        return new_rocket.reached_orbit;
+    }
+    if !new_rocket.reached_orbit {
+        // Neither makes it to orbit, so reward getting closer to it
+        return new_rocket.periapsis > old_rocket.periapsis;
+    }
+    if new_rocket.leftover_delta_v < old_rocket.leftover_delta_v {
+        return false;
+    }
+    if (new_rocket.leftover_delta_v - old_rocket.leftover_delta_v).abs() < 0.1 &&
+        new_rocket.part_count >= old_rocket.part_count {
+        return false;
+    }
+    true
+}
+
+
+fn optimize_rocket(starting_rocket: &[Part], catalogue: &[Part], iterations: usize, site: &LaunchSite, ascent: &AscentMode, objective: Objective) -> Vec<Part> {
     let mut current_rocket: Vec<Part> = starting_rocket.to_vec();
-    let mut current_info = analyze_rocket(&current_rocket);
+    let mut current_info = analyze_rocket(&Vessel::from(current_rocket.as_slice()), site, ascent);
     print_rocket_info(&current_info);
 
     let mut i = 0;
+    let mut failed = 0;
     while i < iterations {
-        let rocket_permutation = permute_parts(&current_rocket);
-        let permutation_info = analyze_rocket(&rocket_permutation);
+        let Some(rocket_permutation) = permute_parts(&current_rocket, catalogue) else {
+            failed += 1;
+            i += 1;
+            continue;
+        };
+        let permutation_info = analyze_rocket(&Vessel::from(rocket_permutation.as_slice()), site, ascent);
         
-        if compare_rockets(&current_info, &permutation_info)  {
+        if compare_rockets(&current_info, &permutation_info, ascent, objective)  {
             current_rocket = rocket_permutation;
             current_info = permutation_info;
             current_info.stages = sort_rocket(&current_info.stages);
@@ -124,25 +312,101 @@ fn optimize_rocket(starting_rocket: &[Part], iterations: usize) {
                 print!(" | TWR (2. STAGE): {:.1}", current_info.stage_info[1].twr);
             }
             print!(" | Alt: {} km", (current_info.final_altitude / 1000.0) as i64);
+            print!(" | Pe: {} km", (current_info.periapsis / 1000.0) as i64);
+            if current_info.reached_orbit {
+                print!(" | Leftover: {}m/s", current_info.leftover_delta_v as i32);
+            }
             print!("\n\n");
         }
         i += 1;
     }
 
     print_rocket_info(&current_info);
+    if failed > 0 {
+        println!("FAILED PERMUTATIONS: {failed} of {iterations} found no change that gives a valid stack");
+    }
     println!("FINAL DELTA-V: {}m/s", current_info.delta_v as i32);
+    current_rocket
 }
 
 
 #[bench]
 fn benchmark(b: &mut test::Bencher) {
-    b.iter(|| optimize_rocket(DEFAULT_ROCKET_1, 10000));
+    let catalogue = stock_catalogue();
+    let rocket = find_parts(&catalogue, DEFAULT_ROCKET_1).unwrap();
+    let body = find_body(&stock_bodies(), "Kerbin").unwrap();
+    let ksc = LaunchSite { body, latitude: KSC_LATITUDE, altitude: KSC_ALTITUDE, inclination: 0.0 };
+    b.iter(|| optimize_rocket(&rocket, &catalogue, 10000, &ksc, &AscentMode::Vertical, Objective::Altitude));
 }
 
 
 fn main() {
     let args = Args::parse();
 
-    optimize_rocket(DEFAULT_ROCKET_1, args.count);
+    let mut bodies = stock_bodies();
+    if let Some(path) = &args.bodies {
+        match load_system(path, &bodies) {
+            Ok(system) => add_bodies(&mut bodies, system),
+            Err(error) => {
+                eprintln!("Invalid planetary system: {error}");
+                process::exit(1);
+            }
+        }
+    }
+    let body = match find_body(&bodies, &args.body) {
+        Ok(body) => body,
+        Err(error) => {
+            eprintln!("Invalid body: {error}");
+            process::exit(1);
+        }
+    };
+    let site = LaunchSite {
+        body,
+        latitude: args.latitude,
+        altitude: args.launch_altitude,
+        inclination: args.inclination,
+    };
+    if let Err(error) = site.validate() {
+        eprintln!("Invalid launch site: {error}");
+        process::exit(1);
+    }
+
+    let mut catalogue = stock_catalogue();
+    if let Some(path) = &args.parts {
+        match load_catalogue(path) {
+            Ok(parts) => add_parts(&mut catalogue, parts),
+            Err(error) => {
+                eprintln!("Invalid part catalogue: {error}");
+                process::exit(1);
+            }
+        }
+    }
+    let rocket = match &args.craft {
+        Some(path) => load_craft(path, &catalogue).map(|mut rocket| {
+            apply_symmetry(&mut rocket);
+            rocket
+        }),
+        None if matches!(args.ascent, Ascent::HorizontalTakeoff) => find_parts(&catalogue, DEFAULT_SPACEPLANE),
+        None => find_parts(&catalogue, DEFAULT_ROCKET_1),
+    };
+    let rocket = match rocket {
+        Ok(rocket) => rocket,
+        Err(error) => {
+            eprintln!("Invalid starting rocket: {error}");
+            process::exit(1);
+        }
+    };
+    if let Some(error) = Vessel::from(rocket.as_slice()).stack_errors().first() {
+        eprintln!("Invalid starting rocket: {error}");
+        process::exit(1);
+    }
+
+    let best_rocket = optimize_rocket(&rocket, &catalogue, args.count, &site, &args.ascent_mode(), args.objective);
+    if let Some(path) = &args.save_craft {
+        let name = path.file_stem().map_or("Optimized Rocket".to_string(), |stem| stem.to_string_lossy().to_string());
+        if let Err(error) = save_craft(path, &best_rocket, &name) {
+            eprintln!("Could not save the rocket: {error}");
+            process::exit(1);
+        }
+    }
 }
- 
\ No newline at end of file
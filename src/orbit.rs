@@ -0,0 +1,200 @@
+use std::f32::consts::PI;
+
+use crate::celestial_body::CelestialBody;
+use crate::rocket_analysis::FlightState;
+
+
+/// Keplerian orbit around a celestial body, as described by its apsides
+#[derive(Debug, Copy, Clone)]
+pub struct Orbit {
+    /// Highest altitude above the surface in m. Infinite for escape trajectories, including orbits that leave the
+    /// sphere of influence.
+    pub apoapsis: f32,
+    /// Lowest altitude above the surface in m
+    pub periapsis: f32,
+    pub eccentricity: f32,
+}
+
+
+impl Orbit {
+    pub fn from_state(state: &FlightState, body: &CelestialBody) -> Orbit {
+        // Specific orbital energy and angular momentum
+        let energy = 0.5 * state.speed() * state.speed() - body.gm / state.radius;
+        let h = state.radius * state.tangential_velocity;
+
+        let eccentricity = (1.0 + 2.0 * energy * h * h / (body.gm * body.gm)).max(0.0).sqrt();
+        // Periapsis from the conic equation, which is also valid for parabolic and hyperbolic trajectories
+        let periapsis_radius = h * h / (body.gm * (1.0 + eccentricity));
+        // Orbits that reach beyond the sphere of influence escape the body just like open trajectories. The apoapsis
+        // follows from the semi-major axis, which also covers vessels that fall straight down.
+        let apoapsis_radius = if energy < 0.0 {
+            -body.gm / (2.0 * energy) * (1.0 + eccentricity)
+        } else {
+            f32::INFINITY
+        };
+        let apoapsis_radius = if apoapsis_radius < body.soi { apoapsis_radius } else { f32::INFINITY };
+
+        Orbit {
+            apoapsis: apoapsis_radius - body.radius,
+            periapsis: periapsis_radius - body.radius,
+            eccentricity,
+        }
+    }
+
+    /// True if the orbit is closed and never dips into the atmosphere or below the surface
+    pub fn is_stable(&self, body: &CelestialBody) -> bool {
+        self.apoapsis.is_finite() && self.periapsis > body.atmosphere_height()
+    }
+
+    /// Speed in m/s at the apoapsis
+    fn apoapsis_speed(&self, body: &CelestialBody) -> f32 {
+        let periapsis_radius = body.radius + self.periapsis;
+        (body.gm * periapsis_radius * (1.0 + self.eccentricity)).sqrt() / (body.radius + self.apoapsis)
+    }
+
+    /// True anomaly in radians of the point at a distance `radius` from the center of the body, on the way up
+    fn outbound_true_anomaly(&self, radius: f32, body: &CelestialBody) -> f32 {
+        let semi_latus_rectum = (body.radius + self.periapsis) * (1.0 + self.eccentricity);
+        ((semi_latus_rectum / radius - 1.0) / self.eccentricity).clamp(-1.0, 1.0).acos()
+    }
+
+    /// Time in s that it takes to coast up to the apoapsis from a distance `radius` from the center of the body.
+    /// Vessels that are on the way down, with a negative `radial_velocity`, pass the periapsis first.
+    pub fn time_to_apoapsis(&self, radius: f32, radial_velocity: f32, body: &CelestialBody) -> f32 {
+        if self.eccentricity < 1e-6 {
+            return 0.0;
+        }
+        let semi_major_axis = body.radius + (self.apoapsis + self.periapsis) / 2.0;
+        // The eccentric anomaly follows from the radius, and the mean anomaly from Kepler's equation
+        let eccentric_anomaly = ((1.0 - radius / semi_major_axis) / self.eccentricity).clamp(-1.0, 1.0).acos();
+        let mean_anomaly = eccentric_anomaly - self.eccentricity * eccentric_anomaly.sin();
+        let mean_motion = (body.gm / semi_major_axis.powi(3)).sqrt();
+        if radial_velocity < 0.0 {
+            // The time from the periapsis up to this point is the time that is left down to it
+            (mean_anomaly + PI) / mean_motion
+        } else {
+            (PI - mean_anomaly) / mean_motion
+        }
+    }
+
+    /// State of a vessel in `state` once it has coasted up to the apoapsis
+    pub fn coast_to_apoapsis(&self, state: &FlightState, body: &CelestialBody) -> FlightState {
+        let true_anomaly = self.outbound_true_anomaly(state.radius, body);
+        let travelled = if self.eccentricity < 1e-6 {
+            0.0
+        } else if state.radial_velocity < 0.0 {
+            PI + true_anomaly
+        } else {
+            PI - true_anomaly
+        };
+        FlightState {
+            radius: body.radius + self.apoapsis,
+            angle: state.angle + travelled,
+            radial_velocity: 0.0,
+            tangential_velocity: self.apoapsis_speed(body),
+        }
+    }
+
+    /// Delta-v in m/s of a prograde burn at the apoapsis that makes the orbit circular
+    pub fn circularization_delta_v(&self, body: &CelestialBody) -> f32 {
+        (body.gm / (body.radius + self.apoapsis)).sqrt() - self.apoapsis_speed(body)
+    }
+
+    /// Orbit after a prograde burn of `delta_v` m/s at the apoapsis
+    pub fn burn_at_apoapsis(&self, delta_v: f32, body: &CelestialBody) -> Orbit {
+        let state = FlightState {
+            radius: body.radius + self.apoapsis,
+            angle: 0.0,
+            radial_velocity: 0.0,
+            tangential_velocity: self.apoapsis_speed(body) + delta_v,
+        };
+        Orbit::from_state(&state, body)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planetary_system::stock_bodies;
+
+    fn kerbin() -> CelestialBody {
+        stock_bodies().into_iter().find(|body| body.name == "Kerbin").unwrap()
+    }
+
+    /// Vessel at the periapsis of an orbit from 100 km up to 500 km above Kerbin
+    fn elliptical_state(body: &CelestialBody) -> FlightState {
+        let (periapsis_radius, apoapsis_radius) = (body.radius + 100_000.0, body.radius + 500_000.0);
+        let semi_major_axis = (periapsis_radius + apoapsis_radius) / 2.0;
+        let speed = (body.gm * (2.0 / periapsis_radius - 1.0 / semi_major_axis)).sqrt();
+        FlightState { radius: periapsis_radius, angle: 0.0, radial_velocity: 0.0, tangential_velocity: speed }
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() < tolerance, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn describes_a_circular_orbit() {
+        let body = kerbin();
+        let radius = body.radius + 100_000.0;
+        let state = FlightState { radius, angle: 0.0, radial_velocity: 0.0, tangential_velocity: (body.gm / radius).sqrt() };
+        let orbit = Orbit::from_state(&state, &body);
+        assert_close(orbit.eccentricity, 0.0, 2e-3);
+        assert_close(orbit.apoapsis, 100_000.0, 1000.0);
+        assert_close(orbit.periapsis, 100_000.0, 1000.0);
+        assert!(orbit.is_stable(&body));
+    }
+
+    #[test]
+    fn describes_an_elliptical_orbit() {
+        let body = kerbin();
+        let orbit = Orbit::from_state(&elliptical_state(&body), &body);
+        assert_close(orbit.eccentricity, 400_000.0 / (2.0 * body.radius + 600_000.0), 1e-3);
+        assert_close(orbit.apoapsis, 500_000.0, 1000.0);
+        assert_close(orbit.periapsis, 100_000.0, 1000.0);
+
+        // From the periapsis, the apoapsis is half an orbit away
+        let semi_major_axis = body.radius + 300_000.0;
+        let period = 2.0 * PI * (semi_major_axis.powi(3) / body.gm).sqrt();
+        assert_close(orbit.time_to_apoapsis(body.radius + 100_000.0, 0.0, &body), period / 2.0, 1.0);
+    }
+
+    #[test]
+    fn times_the_coast_on_the_way_up_and_down() {
+        let body = kerbin();
+        let orbit = Orbit::from_state(&elliptical_state(&body), &body);
+        // At a distance of one semi-major axis, the eccentric anomaly is a quarter turn
+        let semi_major_axis = body.radius + 300_000.0;
+        let mean_motion = (body.gm / semi_major_axis.powi(3)).sqrt();
+        let up = orbit.time_to_apoapsis(semi_major_axis, 100.0, &body);
+        let down = orbit.time_to_apoapsis(semi_major_axis, -100.0, &body);
+        assert_close(up, (PI / 2.0 + orbit.eccentricity) / mean_motion, 1.0);
+        assert_close(down, (3.0 * PI / 2.0 - orbit.eccentricity) / mean_motion, 1.0);
+    }
+
+    #[test]
+    fn coasts_to_the_apoapsis() {
+        let body = kerbin();
+        let state = elliptical_state(&body);
+        let orbit = Orbit::from_state(&state, &body);
+        let coasted = orbit.coast_to_apoapsis(&state, &body);
+        assert_close(coasted.radius, body.radius + 500_000.0, 1000.0);
+        assert_eq!(coasted.radial_velocity, 0.0);
+        assert_close(coasted.angle, PI, 1e-3);
+        // The angular momentum stays the same
+        assert_close(coasted.radius * coasted.tangential_velocity / (state.radius * state.tangential_velocity), 1.0, 1e-3);
+    }
+
+    #[test]
+    fn circularizes_at_the_apoapsis() {
+        let body = kerbin();
+        let orbit = Orbit::from_state(&elliptical_state(&body), &body);
+        let delta_v = orbit.circularization_delta_v(&body);
+        assert!(delta_v > 0.0);
+        let circular = orbit.burn_at_apoapsis(delta_v, &body);
+        assert_close(circular.eccentricity, 0.0, 2e-3);
+        assert_close(circular.periapsis, 500_000.0, 1000.0);
+        assert_close(circular.apoapsis, 500_000.0, 1000.0);
+    }
+}
@@ -0,0 +1,377 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config_node::{parse_config, ConfigNode};
+use crate::float_curve::FloatCurve;
+use crate::parts::{Part, PartVariant, SizeClass};
+use crate::parts::PartVariant::*;
+use crate::resources::{FlowMode, Resource, register_resource};
+
+
+/// KSP gives intakes an area that is scaled for its own airflow formula. Scaled by this, it becomes the inlet area.
+const INTAKE_AREA_SCALE: f32 = 30.0;
+
+/// Wing area in m^2 per unit of KSP's lift coefficient of lifting surfaces
+const WING_AREA_PER_LIFT: f32 = 2.0;
+
+
+/// Loads the parts from KSP part configs. `path` is either a single .cfg file, or a directory like GameData that is
+/// searched for .cfg files. The resource definitions of all files are registered first, so that the parts can use
+/// them. Files, resources, parts and modules that cannot be used are skipped with a warning.
+pub fn load_part_configs(path: &Path) -> Result<Vec<Part>, String> {
+    let mut warnings = Vec::new();
+    let parts = read_part_configs(path, &mut warnings);
+    for warning in warnings {
+        eprintln!("WARNING: {warning}");
+    }
+    parts
+}
+
+
+/// Loads the parts like `load_part_configs`, but collects the warnings instead of printing them
+fn read_part_configs(path: &Path, warnings: &mut Vec<String>) -> Result<Vec<Part>, String> {
+    let mut files = Vec::new();
+    find_config_files(path, &mut files).map_err(|error| format!("{}: {error}", path.display()))?;
+    files.sort();
+
+    let mut roots = Vec::new();
+    for file in files {
+        let source = file.display().to_string();
+        match fs::read_to_string(&file).map_err(|error| error.to_string()).and_then(|text| parse_config(&text)) {
+            Ok(root) => roots.push((source, root)),
+            Err(error) => warnings.push(format!("{source}: skipping the file: {error}")),
+        }
+    }
+
+    for (source, root) in &roots {
+        for node in root.nodes_named("RESOURCE_DEFINITION") {
+            let name = node.value("name").unwrap_or("?");
+            if let Err(error) = register_resource_definition(node) {
+                warnings.push(format!("{source}: {name}: skipping the resource: {error}"));
+            }
+        }
+    }
+
+    let mut parts: Vec<Part> = Vec::new();
+    for (source, root) in &roots {
+        for node in root.nodes_named("PART") {
+            let name = node.value("name").unwrap_or("?");
+            match part_from_config(node, source, warnings) {
+                Ok(part) if parts.iter().any(|other| other.name == part.name) => {
+                    warnings.push(format!("{source}: {name}: skipping the part, because there already is a part with this name"));
+                }
+                Ok(part) => parts.push(part),
+                Err(error) => warnings.push(format!("{source}: {name}: skipping the part: {error}")),
+            }
+        }
+    }
+    Ok(parts)
+}
+
+
+fn find_config_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            find_config_files(&entry?.path(), files)?;
+        }
+    } else if path.extension().is_some_and(|extension| extension == "cfg") {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+
+/// Registers the resource of a RESOURCE_DEFINITION node. KSP's flow modes are mapped to the closest one that is
+/// simulated.
+fn register_resource_definition(node: &ConfigNode) -> Result<(), String> {
+    let name = node.value("name").ok_or("the resource has no name")?;
+    let density = node.number("density")?.ok_or("density is missing")?;
+    let flow_mode = match node.value("flowMode").ok_or("flowMode is missing")? {
+        "NO_FLOW" => FlowMode::NoFlow,
+        "STACK_PRIORITY_SEARCH" | "STAGE_STACK_FLOW" | "STAGE_STACK_FLOW_BALANCE" => FlowMode::Stack,
+        "ALL_VESSEL" | "ALL_VESSEL_BALANCE" | "STAGE_PRIORITY_FLOW" | "STAGE_PRIORITY_FLOW_BALANCE" => FlowMode::Vessel,
+        flow_mode => return Err(format!("the flow mode {flow_mode} is not supported")),
+    };
+    register_resource(name, density, flow_mode).map(|_| ())
+}
+
+
+/// Size of a stack node, as given by the last of the seven numbers that define it. Nodes without a size are 1.25 m.
+/// KSP numbers the sizes 0 (0.625 m), 1 (1.25 m), 2 (2.5 m) and 3 (3.75 m). The 1.875 m size has no number of its
+/// own in the stock configs, and is read from the size 1.5 that mods give it.
+fn node_size(definition: &str) -> Result<SizeClass, String> {
+    let size = definition.split(',').nth(6).map_or("1", str::trim);
+    match size {
+        "0" => Ok(SizeClass::Tiny),
+        "1" => Ok(SizeClass::Small),
+        "1.5" => Ok(SizeClass::Medium),
+        "2" => Ok(SizeClass::Large),
+        "3" => Ok(SizeClass::ExtraLarge),
+        _ => Err(format!("the stack node size {size} is not supported")),
+    }
+}
+
+
+/// Reads the "key = time value" entries of a curve node. The tangents that may follow the value are kept.
+fn curve_keys(curve: &ConfigNode) -> Result<Vec<Vec<f32>>, String> {
+    curve.values_named("key").map(|key| key.split_whitespace().map(|n| n.parse::<f32>()).collect::<Result<_, _>>()
+        .map_err(|_| format!("{} has the invalid key {key}", curve.name))
+    ).collect()
+}
+
+
+/// Reads the curve `name` of a module if the module switches it on with `switch`, and otherwise gives None
+fn optional_curve(module: &ConfigNode, name: &str, switch: &str) -> Result<Option<FloatCurve>, String> {
+    match module.nodes_named(name).next() {
+        Some(curve) if module.value(switch).is_some_and(|value| value.eq_ignore_ascii_case("true")) => {
+            Ok(Some(FloatCurve::from_keys(&curve_keys(curve)?).map_err(|error| format!("{name}: {error}"))?))
+        }
+        _ => Ok(None),
+    }
+}
+
+
+fn engine_from_config(module: &ConfigNode) -> Result<PartVariant, String> {
+    let thrust_vac = module.number("maxThrust")?.ok_or("maxThrust is missing")?;
+    let curve = module.nodes_named("atmosphereCurve").next().ok_or("atmosphereCurve is missing")?;
+    // The curve maps the pressure in atm to the specific impulse
+    let isp_curve = FloatCurve::from_keys(&curve_keys(curve)?).map_err(|error| format!("atmosphereCurve: {error}"))?;
+
+    let mut propellants = Vec::new();
+    for propellant in module.nodes_named("PROPELLANT") {
+        let name = propellant.value("name").unwrap_or("");
+        let resource = Resource::from_name(name).ok_or(format!("the propellant {name} is not supported"))?;
+        let ratio = propellant.number("ratio")?.ok_or(format!("the propellant {name} has no ratio"))?;
+        if !(ratio.is_finite() && ratio > 0.0) {
+            return Err(format!("the propellant {name} has the invalid ratio {ratio}"));
+        }
+        propellants.push((resource, ratio));
+    }
+    if propellants.is_empty() {
+        return Err("the engine has no propellants".to_string());
+    }
+    if thrust_vac <= 0.0 || isp_curve.evaluate(0.0) <= 0.0 {
+        return Err("the engine produces no thrust".to_string());
+    }
+
+    if propellants.iter().any(|(resource, _)| *resource == Resource::SOLID_FUEL) {
+        // The thrust curve is only used if it is switched on
+        let thrust_curve = optional_curve(module, "thrustCurve", "useThrustCurve")?;
+        Ok(SolidBooster { thrust_vac, isp_curve, propellants, thrust_curve })
+    } else if propellants.iter().any(|(resource, _)| *resource == Resource::INTAKE_AIR) {
+        // Without its curves, the thrust of a jet does not depend on the speed or the air density
+        let constant = || FloatCurve::from_keys(&[[0.0, 1.0]]).unwrap();
+        let velocity_curve = optional_curve(module, "velCurve", "useVelCurve")?.unwrap_or_else(constant);
+        let density_curve = optional_curve(module, "atmCurve", "useAtmCurve")?.unwrap_or_else(constant);
+        Ok(JetEngine { thrust_max: thrust_vac, isp_curve, propellants, velocity_curve, density_curve })
+    } else {
+        Ok(Engine { thrust_vac, isp_curve, propellants })
+    }
+}
+
+
+/// Builds a part from a PART node. The first supported module decides what kind of part it is. Parts without one are
+/// tanks if they store resources, or structural parts if they have stack nodes.
+fn part_from_config(node: &ConfigNode, source: &str, warnings: &mut Vec<String>) -> Result<Part, String> {
+    let name = node.value("name").ok_or("the part has no name")?;
+    let mass = node.number("mass")?.ok_or("mass is missing")?;
+    if !(mass.is_finite() && mass > 0.0) {
+        return Err(format!("mass must be larger than zero, not {mass}"));
+    }
+
+    let mut resources = Vec::new();
+    for resource in node.nodes_named("RESOURCE") {
+        let resource_name = resource.value("name").unwrap_or("");
+        let amount = resource.number("amount")?.unwrap_or(0.0);
+        match Resource::from_name(resource_name) {
+            Some(resource) => resources.push((resource, amount)),
+            None => warnings.push(format!("{source}: {name}: skipping the unsupported resource {resource_name}")),
+        }
+    }
+
+    let top_node = node.value("node_stack_top").map(node_size).transpose()?;
+    let bottom_nodes: Vec<SizeClass> = node.values_starting_with("node_stack_bottom").map(node_size)
+        .collect::<Result<_, _>>()?;
+
+    let mut variant = None;
+    for module in node.nodes_named("MODULE") {
+        let module_name = module.value("name").unwrap_or("");
+        let module_variant = match module_name {
+            "ModuleEngines" | "ModuleEnginesFX" => engine_from_config(module)?,
+            "ModuleResourceIntake" => {
+                let area = module.number("area")?.ok_or("the intake has no area")?;
+                Intake { area: area * INTAKE_AREA_SCALE }
+            }
+            "ModuleLiftingSurface" | "ModuleControlSurface" => {
+                let lift = module.number("deflectionLiftCoeff")?.ok_or("the wing has no deflectionLiftCoeff")?;
+                Wing { area: lift * WING_AREA_PER_LIFT }
+            }
+            "ModuleDecouple" => Decoupler,
+            "ModuleAnchoredDecoupler" => RadialDecoupler { crossfeed: false },
+            "ModuleCommand" => CommandPod,
+            "ModuleParachute" => Parachute,
+            _ => {
+                warnings.push(format!("{source}: {name}: skipping the unsupported module {module_name}"));
+                continue;
+            }
+        };
+        if variant.is_none() {
+            variant = Some(module_variant);
+        } else {
+            warnings.push(format!("{source}: {name}: skipping the module {module_name}, since the part already has a function"));
+        }
+    }
+    let variant = match variant {
+        Some(variant) => variant,
+        None if bottom_nodes.len() > 1 => MultiCoupler { nodes: bottom_nodes.len() as u32 },
+        None if !resources.is_empty() => Tank,
+        None if top_node.is_some() || !bottom_nodes.is_empty() => Adapter,
+        None => return Err("the part has no function that is supported".to_string()),
+    };
+
+    // The configs describe drag with meshes, so the frontal area is estimated from the largest stack node instead
+    let size = top_node.iter().chain(&bottom_nodes).map(SizeClass::area).fold(0.0, f32::max);
+    let (drag_area, drag_coefficient) = match variant {
+        _ if size == 0.0 => (0.1, 0.50),
+        SolidBooster { .. } | Engine { .. } | JetEngine { .. } => (size, 0.30),
+        CommandPod => (size, 0.50),
+        Parachute => (size, 0.20),
+        _ => (size, 0.05),
+    };
+
+    Ok(Part {
+        name: name.to_string(),
+        // KSP replaces underscores in the names of parts when it loads them
+        ksp_name: name.replace('_', "."),
+        mass,
+        drag_area,
+        drag_coefficient,
+        resources,
+        variant,
+        symmetry: 1,
+        top_node,
+        bottom_node: bottom_nodes.first().copied(),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_parts() -> (Vec<Part>, Vec<String>) {
+        let mut warnings = Vec::new();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/GameData");
+        let parts = read_part_configs(&path, &mut warnings).unwrap();
+        (parts, warnings)
+    }
+
+    fn find<'a>(parts: &'a [Part], name: &str) -> &'a Part {
+        parts.iter().find(|part| part.name == name).unwrap_or_else(|| panic!("{name} was not loaded"))
+    }
+
+    fn single_part(text: &str) -> Result<Part, String> {
+        let root = parse_config(text).unwrap();
+        let part = part_from_config(root.nodes_named("PART").next().unwrap(), "test.cfg", &mut Vec::new());
+        part
+    }
+
+    fn resource(name: &str) -> Resource {
+        Resource::from_name(name).unwrap()
+    }
+
+    #[test]
+    fn loads_the_fixture_parts() {
+        let (parts, _) = fixture_parts();
+        let mut names: Vec<&str> = parts.iter().map(|part| part.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, [
+            "adapterLargeSmallBi", "exampleJetEngine", "exampleOreTank", "exampleProgressiveBooster", "fuelTank",
+            "liquidEngine2", "mk1pod_v2", "parachuteSingle", "radialDecoupler", "size2Decoupler", "solidBooster1-1",
+        ]);
+
+        let engine = find(&parts, "liquidEngine2");
+        assert!(matches!(engine.variant, Engine { thrust_vac, .. } if thrust_vac == 215.0));
+        assert_eq!(engine.variant.propellants(), [(resource("LiquidFuel"), 0.9), (resource("Oxidizer"), 1.1)]);
+        assert_eq!(engine.variant.isp(0.0), 320.0);
+        assert_eq!(engine.mass, 1.5);
+        assert_eq!((engine.top_node, engine.bottom_node), (Some(SizeClass::Small), Some(SizeClass::Small)));
+
+        let booster = find(&parts, "solidBooster1-1");
+        assert!(matches!(booster.variant, SolidBooster { thrust_curve: None, .. }));
+        assert!(find(&parts, "exampleProgressiveBooster").variant.thrust_curve().is_some());
+        assert!(matches!(find(&parts, "exampleJetEngine").variant, JetEngine { thrust_max, .. } if thrust_max == 90.0));
+
+        let tank = find(&parts, "fuelTank");
+        assert!(matches!(tank.variant, Tank));
+        assert_eq!(tank.resources, [(resource("LiquidFuel"), 180.0), (resource("Oxidizer"), 220.0)]);
+
+        let pod = find(&parts, "mk1pod_v2");
+        assert!(matches!(pod.variant, CommandPod));
+        assert_eq!(pod.ksp_name, "mk1pod.v2");
+        assert_eq!(pod.resources, [(resource("ElectricCharge"), 50.0), (resource("MonoPropellant"), 10.0)]);
+
+        assert!(matches!(find(&parts, "parachuteSingle").variant, Parachute));
+        let decoupler = find(&parts, "size2Decoupler");
+        assert!(matches!(decoupler.variant, Decoupler));
+        assert_eq!(decoupler.top_node, Some(SizeClass::Large));
+        let radial = find(&parts, "radialDecoupler");
+        assert!(matches!(radial.variant, RadialDecoupler { crossfeed: false }));
+        assert!(radial.is_surface_mounted());
+        assert!(matches!(find(&parts, "adapterLargeSmallBi").variant, MultiCoupler { nodes: 2 }));
+    }
+
+    #[test]
+    fn registers_resource_definitions() {
+        let (parts, _) = fixture_parts();
+        let ore = resource("ExampleOre");
+        assert_eq!(ore.density(), 0.01);
+        assert_eq!(ore.flow_mode(), FlowMode::Stack);
+        let tank = find(&parts, "exampleOreTank");
+        assert_eq!(tank.resources, [(ore, 150.0)]);
+        assert_eq!(tank.top_node, Some(SizeClass::Medium));
+    }
+
+    #[test]
+    fn skips_what_cannot_be_used_with_a_warning() {
+        let (_, warnings) = fixture_parts();
+        let warned = |text: &str| warnings.iter().any(|warning| warning.contains(text));
+        assert!(warned("broken.cfg: skipping the file"));
+        assert!(warned("liquidEngine2: skipping the unsupported module ModuleGimbal"));
+        assert!(warned("liquidEngine2: skipping the unsupported module ModuleAlternator"));
+        assert!(warned("mk1pod_v2: skipping the unsupported module ModuleReactionWheel"));
+        assert!(warned("size2Decoupler: skipping the unsupported module ModuleToggleCrossfeed"));
+    }
+
+    #[test]
+    fn rejects_invalid_propellant_ratios() {
+        for ratio in ["0", "-1", "NaN", "inf"] {
+            let text = format!(
+                "PART {{\nname = bad\nmass = 1\nMODULE {{\nname = ModuleEngines\nmaxThrust = 100\n\
+                PROPELLANT {{\nname = LiquidFuel\nratio = {ratio}\n}}\natmosphereCurve {{\nkey = 0 300\n}}\n}}\n}}\n"
+            );
+            let error = single_part(&text).unwrap_err();
+            assert!(error.contains("the propellant LiquidFuel has the invalid ratio"), "{error}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_masses() {
+        for mass in ["0", "-0.5", "NaN"] {
+            let error = single_part(&format!("PART {{\nname = bad\nmass = {mass}\nnode_stack_top = 0, 0, 0, 0, 1, 0, 1\n}}\n")).unwrap_err();
+            assert!(error.contains("mass must be larger than zero"), "{error}");
+        }
+    }
+
+    #[test]
+    fn maps_node_sizes() {
+        let size = |n: &str| node_size(&format!("0.0, 0.5, 0.0, 0.0, 1.0, 0.0, {n}"));
+        assert_eq!(size("0"), Ok(SizeClass::Tiny));
+        assert_eq!(size("1"), Ok(SizeClass::Small));
+        assert_eq!(size("1.5"), Ok(SizeClass::Medium));
+        assert_eq!(size("2"), Ok(SizeClass::Large));
+        assert_eq!(size("3"), Ok(SizeClass::ExtraLarge));
+        assert!(size("4").is_err());
+        assert_eq!(node_size("0.0, 0.5, 0.0, 0.0, 1.0, 0.0"), Ok(SizeClass::Small));
+    }
+}
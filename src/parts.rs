@@ -1,115 +1,365 @@
 use core::fmt;
 
-pub const SOLID_FUEL_DENSITY: f32 = 0.0075;
-pub const EFF_FUEL_DENSITY: f32 = 0.005 * 20.0 / 9.0;
+use crate::float_curve::FloatCurve;
+use crate::rocket_analysis::G0;
+use crate::resources::{FlowMode, Resource, ResourceAmounts, Resources, resource_amounts, resource_mass};
+
+/// Diameter class of a stack attachment node. Only nodes of the same class can be joined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeClass {
+    /// 0.625 m
+    Tiny,
+    /// 1.25 m
+    Small,
+    /// 1.875 m
+    Medium,
+    /// 2.5 m
+    Large,
+    /// 3.75 m
+    ExtraLarge,
+}
+use SizeClass::*;
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum PartVariant {
     SolidBooster {
-        thrust_asl: f32,
         thrust_vac: f32,
-        isp_asl: f32,
-        #[allow(unused)] // We want isp_vac in the database, but don't use it currently.
-        isp_vac: f32,
-        fuel: f32,
+        /// Specific impulse in s over the pressure in atm
+        isp_curve: FloatCurve,
+        propellants: Resources,
+        /// Multiplier of the fuel flow over the fraction of fuel left in the booster. Without a curve, the thrust
+        /// stays constant until burnout.
+        thrust_curve: Option<FloatCurve>,
     },
     Engine {
-        thrust_asl: f32,
         thrust_vac: f32,
-        isp_asl: f32,
-        #[allow(unused)]
-        isp_vac: f32,
+        /// Specific impulse in s over the pressure in atm
+        isp_curve: FloatCurve,
+        propellants: Resources,
+    },
+    /// Air-breathing engine, which burns its propellants with the air from the intakes. Like in KSP, the thrust and
+    /// fuel flow follow curves over the flight speed and the air density.
+    JetEngine {
+        /// Thrust in kN before the multipliers of the curves are applied
+        thrust_max: f32,
+        /// Specific impulse in s over the pressure in atm. The air does not count towards the propellant mass.
+        isp_curve: FloatCurve,
+        propellants: Resources,
+        /// Thrust multiplier over the Mach number
+        velocity_curve: FloatCurve,
+        /// Thrust multiplier over the air density, relative to `REFERENCE_DENSITY`
+        density_curve: FloatCurve,
+    },
+    /// Takes in air for jet engines through an inlet of `area` m^2
+    Intake {
+        area: f32,
     },
-    Tank { 
-        fuel: f32,
+    /// Lifting surface with a wing area of `area` m^2
+    Wing {
+        area: f32,
+    },
+    Tank,
+    /// Produces resources at a constant rate in units/s, like a solar panel in full sunlight
+    Generator {
+        output: Resources,
     },
     Decoupler,
+    /// Attaches the stage below it to the side of the stage above it, so that both fire together. With
+    /// crossfeed enabled, the attached stage can feed its fuel to the stage above it.
+    RadialDecoupler {
+        crossfeed: bool,
+    },
+    /// Lets the stage it is mounted on feed its fuel to the stage above it
+    FuelLine,
+    /// Structural part that joins a stack to a number of identical stacks below it, for engine clusters. The
+    /// parts below it are placed in symmetry.
+    MultiCoupler {
+        nodes: u32,
+    },
+    Adapter,
     Parachute,
     CommandPod,
 }
 use PartVariant::*;
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Part {
-    pub name: &'static str,
+    pub name: String,
+    /// Internal name of the part in KSP, as used in .craft files
+    pub ksp_name: String,
     pub mass: f32,
+    /// Frontal area in m^2
+    pub drag_area: f32,
+    /// Drag coefficient of the part. In a stack, the parts shield each other, so only the one with the most drag
+    /// counts.
+    pub drag_coefficient: f32,
+    /// Resources stored in the part
+    pub resources: Resources,
     pub variant: PartVariant,
+    /// Number of identical copies that are placed in radial symmetry. All other values are for a single copy.
+    pub symmetry: u32,
+    /// Size of the stack node that the part above attaches to. Parts without stack nodes are surface mounted.
+    pub top_node: Option<SizeClass>,
+    /// Size of the stack node that the part below attaches to
+    pub bottom_node: Option<SizeClass>,
 }
 
 
 pub type Stage = Vec<Part>;
 
 
+/// Air density in kg/m^3 that the density curves of jet engines are relative to, which is the density at sea level
+pub const REFERENCE_DENSITY: f32 = 1.225;
+
+
+/// Symmetry modes that a part can be placed in. A single part has no symmetry.
+pub const SYMMETRY_COUNTS: &[u32] = &[1, 2, 3, 4, 6];
+
+
 impl Part {
-    const fn new(name: &'static str, mass: f32, variant: PartVariant) -> Part {
-        Part {name, mass, variant}
+    pub fn is_surface_mounted(&self) -> bool {
+        self.top_node.is_none() && self.bottom_node.is_none()
+    }
+
+    /// Number of parts that can be stacked below each copy of this part
+    pub fn bottom_nodes(&self) -> u32 {
+        match self.variant {
+            MultiCoupler { nodes } => nodes,
+            _ => 1,
+        }
+    }
+
+    pub fn copies(&self) -> f32 {
+        self.symmetry as f32
+    }
+
+    /// Resources stored in all copies of the part
+    pub fn resource_amounts(&self) -> ResourceAmounts {
+        resource_amounts(&self.resources).scaled(self.copies())
+    }
+
+    /// Consumption of `resource` by all copies at full throttle in units/s
+    pub fn resource_flow(&self, resource: Resource) -> f32 {
+        self.variant.resource_flow(resource) * self.copies()
+    }
+
+    /// Propellant mass flow of all copies at full throttle in t/s
+    pub fn mass_flow(&self) -> f32 {
+        self.variant.mass_flow() * self.copies()
+    }
+
+    /// Thrust of all copies at full throttle in kN at `pressure` atm
+    pub fn thrust(&self, pressure: f32) -> f32 {
+        self.variant.thrust(pressure) * self.copies()
+    }
+}
+
+
+impl PartVariant {
+    /// Propellant mass flow at full throttle in t/s. Like in KSP, the fuel flow is fixed, and the thrust
+    /// varies with the specific impulse instead.
+    pub fn mass_flow(&self) -> f32 {
+        match *self {
+            SolidBooster { thrust_vac, ref isp_curve, .. } | Engine { thrust_vac, ref isp_curve, .. } => {
+                thrust_vac / (isp_curve.evaluate(0.0) * G0)
+            }
+            JetEngine { thrust_max, ref isp_curve, .. } => thrust_max / (isp_curve.evaluate(0.0) * G0),
+            _ => 0.0,
+        }
+    }
+
+    pub fn is_jet(&self) -> bool {
+        matches!(self, JetEngine { .. })
+    }
+
+    /// Multiplier of the thrust and fuel flow of a jet engine at `mach` in air of `density` kg/m^3. Other engines
+    /// do not depend on the air.
+    pub fn air_multiplier(&self, mach: f32, density: f32) -> f32 {
+        match self {
+            JetEngine { velocity_curve, density_curve, .. } => {
+                (velocity_curve.evaluate(mach) * density_curve.evaluate(density / REFERENCE_DENSITY)).max(0.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Mixture ratio of the propellants in units. Only the ratio matters, not the absolute values.
+    pub fn propellants(&self) -> &[(Resource, f32)] {
+        match self {
+            SolidBooster { propellants, .. } | Engine { propellants, .. } | JetEngine { propellants, .. } => propellants,
+            _ => &[],
+        }
+    }
+
+    pub fn thrust_curve(&self) -> Option<&FloatCurve> {
+        match self {
+            SolidBooster { thrust_curve, .. } => thrust_curve.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Resources that the part produces, in units/s
+    pub fn outputs(&self) -> &[(Resource, f32)] {
+        match self {
+            Generator { output } => output,
+            _ => &[],
+        }
+    }
+
+    /// Consumption of `resource` at full throttle in units/s. The intake air of jet engines is not part of the mass
+    /// flow, but its consumption follows from the mixture ratio all the same.
+    pub fn resource_flow(&self, resource: Resource) -> f32 {
+        let propellants = self.propellants();
+        let mixture_mass: f32 = propellants.iter()
+            .filter(|(r, _)| r.flow_mode() != FlowMode::Atmosphere)
+            .map(|(r, ratio)| ratio * r.density())
+            .sum();
+        let ratio: f32 = propellants.iter().filter(|(r, _)| *r == resource).map(|(_, ratio)| ratio).sum();
+        if mixture_mass > 0.0 {
+            self.mass_flow() * ratio / mixture_mass
+        } else {
+            0.0
+        }
+    }
+
+    /// Specific impulse in s at `pressure` atm, as given by the curve
+    pub fn isp(&self, pressure: f32) -> f32 {
+        match *self {
+            SolidBooster { ref isp_curve, .. } | Engine { ref isp_curve, .. } | JetEngine { ref isp_curve, .. } => {
+                isp_curve.evaluate(pressure)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Thrust at full throttle in kN at `pressure` atm, before the multiplier of jet engines. Isp curves with several
+    /// keys can dip below zero between them, so engines in an atmosphere that is too thick merely stop pushing.
+    pub fn thrust(&self, pressure: f32) -> f32 {
+        self.mass_flow() * self.isp(pressure).max(0.0) * G0
+    }
+}
+
+
+impl SizeClass {
+    /// Diameter in m
+    pub fn diameter(&self) -> f32 {
+        match self {
+            Tiny => 0.625,
+            Small => 1.25,
+            Medium => 1.875,
+            Large => 2.5,
+            ExtraLarge => 3.75,
+        }
+    }
+
+    /// Frontal area in m^2
+    pub fn area(&self) -> f32 {
+        std::f32::consts::PI * self.diameter() * self.diameter() / 4.0
+    }
+
+    pub fn from_diameter(diameter: f32) -> Option<SizeClass> {
+        [Tiny, Small, Medium, Large, ExtraLarge].into_iter().find(|size| size.diameter() == diameter)
+    }
+}
+
+
+impl fmt::Display for SizeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}m", self.diameter())
     }
 }
 
 
 impl fmt::Display for Part {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.symmetry > 1 {
+            write!(f, "{}x ", self.symmetry)?;
+        }
         write!(f, "{}", self.name)
     }
 }
 
 
-pub fn part_fuel_mass(parts: &[Part]) -> f32 {
-    parts.iter().map(|part| match part.variant {
-        Tank { fuel, .. } => fuel,
-        _ => 0.0,
-    }).sum()
+/// Returns the total mass of all stored resources in t
+pub fn part_resource_mass(parts: &[Part]) -> f32 {
+    parts.iter().map(|part| resource_mass(&part.resource_amounts())).sum()
 }
 
 
-pub fn part_solid_fuel_mass(parts: &[Part]) -> f32 {
-    parts.iter().map(|part| match part.variant {
-        SolidBooster { fuel, .. } => fuel,
-        _ => 0.0,
-    }).sum()
+pub fn part_mass_dry(parts: &[Part]) -> f32 {
+    parts.iter().map(|part| part.mass * part.copies()).sum()
 }
 
 
-pub fn part_mass_dry(parts: &[Part]) -> f32 {
-    parts.iter().map(|part| part.mass).sum()
+/// Returns the drag coefficient times area (C_d * A) of a stack of parts in m^2. Each column of the stack only counts
+/// with its largest cross-section. Parts in symmetry form columns of their own beside the main one, and surface
+/// mounted parts add their full drag.
+pub fn part_drag_area<'a>(parts: impl IntoIterator<Item = &'a Part>) -> f32 {
+    let mut columns: Vec<(u32, f32)> = Vec::new();
+    let mut surface_drag_area = 0.0;
+    for part in parts {
+        let drag_area = part.drag_area * part.drag_coefficient * part.copies();
+        if part.is_surface_mounted() {
+            surface_drag_area += drag_area;
+            continue;
+        }
+        match columns.iter_mut().find(|(symmetry, _)| *symmetry == part.symmetry) {
+            Some(column) => column.1 = column.1.max(drag_area),
+            None => columns.push((part.symmetry, drag_area)),
+        }
+    }
+    surface_drag_area + columns.iter().map(|(_, drag_area)| drag_area).sum::<f32>()
+}
+
+
+/// Returns the summed inlet area of the intakes in m^2
+pub fn part_intake_area(parts: &[Part]) -> f32 {
+    parts.iter().map(|part| if let Intake { area } = part.variant { area * part.copies() } else { 0.0 }).sum()
+}
+
+
+/// Returns the summed wing area in m^2
+pub fn part_wing_area(parts: &[Part]) -> f32 {
+    parts.iter().map(|part| if let Wing { area } = part.variant { area * part.copies() } else { 0.0 }).sum()
 }
 
 
 pub fn part_mass_wet(parts: &[Part]) -> f32 {
-    let part_mass = part_mass_dry(parts);
-    let fuel_mass = part_fuel_mass(parts) * EFF_FUEL_DENSITY;
-    let solid_fuel_mass = part_solid_fuel_mass(parts) * SOLID_FUEL_DENSITY;
-    part_mass + fuel_mass + solid_fuel_mass
+    part_mass_dry(parts) + part_resource_mass(parts)
 }
 
 
-pub fn rocket_stages(parts: &[Part]) -> Vec<Stage> {
-    // Splits a rocket into stages, as separated by decouplers
-    // Decouplers are included in the lower stage (because they are jettisoned with them)
-    let mut stages = Vec::new();
-    let mut stage_parts = Vec::<Part>::new();
-    for part in parts {
-        stage_parts.push(*part);
-        match part.variant {
-            PartVariant::Decoupler => { stages.push(stage_parts); stage_parts = Vec::new(); }
-            _ => {},
-        }
-    }
-    stages.push(stage_parts);
-    stages
+/// True if the stage is attached radially to the stage above it, instead of being stacked below it
+pub fn is_radial_stage(stage: &[Part]) -> bool {
+    stage.last().is_some_and(|part| matches!(part.variant, RadialDecoupler{..}))
+}
+
+
+/// True if the stage feeds its fuel to the stage above it, through a fuel line or a crossfeed-enabled decoupler.
+/// Fuel only flows between stages that fire together, so this is only possible for radial stages.
+pub fn stage_crossfeeds(stage: &[Part]) -> bool {
+    is_radial_stage(stage) && stage.iter().any(
+        |part| matches!(part.variant, FuelLine | RadialDecoupler{ crossfeed: true })
+    )
 }
 
 
 pub fn sort_stage(parts: &[Part]) -> Vec<Part> {
     let mut sorted: Vec<Part> = Vec::new();
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, SolidBooster{..})));
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Engine{..})));
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Tank{..})));
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, CommandPod)));
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Parachute)));
-    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Decoupler)));
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, SolidBooster{..})).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Engine{..})).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, JetEngine{..})).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Tank)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Intake{..} | Wing{..})).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Generator{..})).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, MultiCoupler{..} | Adapter)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, CommandPod)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Parachute)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, Decoupler)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, FuelLine)).cloned());
+    sorted.extend(parts.iter().filter(|p| matches!(p.variant, RadialDecoupler{..})).cloned());
     assert!(sorted.len() == parts.len());
     sorted
 }
@@ -128,7 +378,7 @@ pub fn print_rocket(stages: &[Stage]) {
     let mut it = stages.iter().peekable();
     while let Some(stage) = it.next() {
         for part in stage {
-            print!("{} ", part.name);
+            print!("{part} ");
         }
         if it.peek().is_some() {
             print!("// ");
@@ -137,49 +387,25 @@ pub fn print_rocket(stages: &[Stage]) {
 }
 
 
-const PART_TD12: Part = Part::new("TD-12", 0.04, Decoupler);
-const PART_RT5: Part = Part::new("RT-5", 0.45, SolidBooster 
-    { fuel: 140.0, thrust_asl: 162.91, thrust_vac: 192.0, isp_asl: 140.0, isp_vac: 165.0 });
-const PART_RT10: Part = Part::new("RT-10", 0.75, SolidBooster
-    { fuel: 375.0, thrust_asl: 197.90, thrust_vac: 227.0, isp_asl: 170.0, isp_vac: 195.0 });
-const PART_BACC: Part = Part::new("BACC", 1.5, SolidBooster
-    { fuel: 820.0, thrust_asl: 250.0, thrust_vac: 300.0, isp_asl: 175.0, isp_vac: 210.0 });
-const PART_LVT30: Part = Part::new("LV-T30", 1.25, Engine
-    {thrust_asl: 205.16, thrust_vac: 240.0, isp_asl: 265.0, isp_vac: 310.0 });
-const PART_LVT45: Part = Part::new("LV-T45", 1.50, Engine
-    {thrust_asl: 167.97, thrust_vac: 215.0, isp_asl: 250.0, isp_vac: 320.0 });
-const PART_LV909: Part = Part::new("LV-909", 0.50, Engine
-    {thrust_asl: 14.78, thrust_vac: 60.0, isp_asl: 85.0, isp_vac: 345.0 });
-const PART_FLT100: Part = Part::new("FL-T100", 0.0625, Tank{ fuel: 45.0 });
-const PART_FLT200: Part = Part::new("FL-T200", 0.125, Tank{ fuel: 90.0 });
-const PART_FLT400: Part = Part::new("FL-T400", 0.25, Tank{ fuel: 180.0 });
-const PART_FLT800: Part = Part::new("FL-T800", 0.50, Tank{ fuel: 360.0 });
-const PART_MK1_POD: Part = Part::new("Mk1 Command Pod", 0.84, CommandPod);
-const PART_MK16_CHUTE: Part = Part::new("Mk16 Parachute", 0.1, Parachute);
-
-
-pub const PART_CATALOGUE: &[Part] = &[
-    PART_TD12,
-    PART_RT5,
-    PART_RT10,
-    PART_BACC,
-    PART_LVT30,
-    PART_LVT45,
-    PART_LV909,
-    PART_FLT100,
-    PART_FLT200,
-    PART_FLT400,
-    PART_FLT800,
-    PART_MK1_POD,
-    PART_MK16_CHUTE,
+/// Parts of the rocket that the optimizer starts from, from the bottom up
+pub const DEFAULT_ROCKET_1: &[&str] = &[
+    "RT-10",
+    "TD-12",
+    "LV-T45",
+    "FL-T100",
+    "Mk1 Command Pod",
+    "Mk16 Parachute",
 ];
 
 
-pub const DEFAULT_ROCKET_1: &[Part] = &[
-    PART_RT10,
-    PART_TD12,
-    PART_LVT45,
-    PART_FLT100,
-    PART_MK1_POD,
-    PART_MK16_CHUTE,
-];
\ No newline at end of file
+/// Parts of the spaceplane that the optimizer starts from for a horizontal takeoff, from the bottom up
+pub const DEFAULT_SPACEPLANE: &[&str] = &[
+    "J-33 Wheesley",
+    "LV-909",
+    "FL-T400",
+    "Mk1 Fuselage",
+    "XM-G50",
+    "Big-S Delta Wing",
+    "Mk1 Command Pod",
+    "Mk16 Parachute",
+];
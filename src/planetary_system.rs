@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::celestial_body::{Atmosphere, CelestialBody};
+use crate::float_curve::FloatCurve;
+
+
+/// The stock bodies, which are always available
+const STOCK_BODIES: &str = include_str!("../bodies/stock.toml");
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SystemFile {
+    body: Vec<BodyEntry>,
+}
+
+
+/// A body as it is written in a definition file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BodyEntry {
+    name: String,
+    radius: f32,
+    gm: f32,
+    rotation_period: f32,
+    soi: Option<f32>,
+    orbit: Option<OrbitEntry>,
+    atmosphere: Option<AtmosphereEntry>,
+}
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrbitEntry {
+    parent: String,
+    semi_major_axis: f32,
+}
+
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtmosphereEntry {
+    height: f32,
+    gas_constant: f32,
+    adiabatic_index: f32,
+    #[serde(default)]
+    oxygen: bool,
+    pressure_curve: Vec<Vec<f32>>,
+    temperature_curve: Vec<Vec<f32>>,
+    density_curve: Option<Vec<Vec<f32>>>,
+}
+
+
+impl BodyEntry {
+    /// Builds the body. The parent that it orbits is looked up in the `earlier` bodies of the file first, and then in
+    /// the `known` ones.
+    fn into_body(self, earlier: &[CelestialBody], known: &[CelestialBody]) -> Result<CelestialBody, String> {
+        if self.name.trim().is_empty() {
+            return Err("the name is empty".to_string());
+        }
+        let radius = positive("radius", self.radius)?;
+        let gm = positive("gm", self.gm)?;
+        let rotation_period = positive("rotation_period", self.rotation_period)?;
+
+        // The sphere of influence is where the gravity of the body starts to dominate over that of its parent
+        let soi = match (self.soi, self.orbit) {
+            (Some(soi), None) => positive("soi", soi)?,
+            (None, Some(orbit)) => {
+                let parent = earlier.iter().chain(known).find(|body| body.name == orbit.parent)
+                    .ok_or(format!("the parent {} must be defined before the body", orbit.parent))?;
+                let semi_major_axis = positive("semi_major_axis", orbit.semi_major_axis)?;
+                if semi_major_axis <= parent.radius + radius {
+                    return Err(format!("the orbit is inside of {}", parent.name));
+                }
+                semi_major_axis * (gm / parent.gm).powf(0.4)
+            }
+            (None, None) => f32::INFINITY,
+            (Some(_), Some(_)) => return Err("soi replaces the orbit".to_string()),
+        };
+        if soi <= radius {
+            return Err("the sphere of influence is inside of the body".to_string());
+        }
+
+        Ok(CelestialBody {
+            name: self.name,
+            radius,
+            gm,
+            rotation_rate: 2.0 * std::f32::consts::PI / rotation_period,
+            soi,
+            atmosphere: self.atmosphere.map(AtmosphereEntry::into_atmosphere).transpose()?,
+        })
+    }
+}
+
+
+impl AtmosphereEntry {
+    fn into_atmosphere(self) -> Result<Atmosphere, String> {
+        let height = positive("height", self.height)?;
+        let gas_constant = positive("gas_constant", self.gas_constant)?;
+        if !(self.adiabatic_index.is_finite() && self.adiabatic_index > 1.0) {
+            return Err(format!("adiabatic_index must be larger than one, not {}", self.adiabatic_index));
+        }
+        let curve = |key: &str, keys: &[Vec<f32>]| FloatCurve::from_keys(keys).map_err(|error| format!("{key}: {error}"));
+        let pressure = curve("pressure_curve", &self.pressure_curve)?;
+        let temperature = curve("temperature_curve", &self.temperature_curve)?;
+        let density = self.density_curve.map(|keys| curve("density_curve", &keys)).transpose()?;
+
+        if pressure.evaluate(0.0) <= 0.0 {
+            return Err("the pressure at the surface must be larger than zero".to_string());
+        }
+        if density.as_ref().is_some_and(|density| density.evaluate(0.0) <= 0.0) {
+            return Err("the density at the surface must be larger than zero".to_string());
+        }
+        // The temperature is checked all the way up, so that a bad tangent cannot send it below zero between the keys
+        for i in 0..=100 {
+            let altitude = height * i as f32 / 100.0;
+            if temperature.evaluate(altitude) <= 0.0 {
+                return Err(format!("the temperature at {altitude}m must be larger than zero"));
+            }
+        }
+
+        Ok(Atmosphere { height, pressure, temperature, density, gas_constant, adiabatic_index: self.adiabatic_index, oxygen: self.oxygen })
+    }
+}
+
+
+fn positive(key: &str, value: f32) -> Result<f32, String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{key} must be larger than zero, not {value}"))
+    }
+}
+
+
+/// Parses a planetary system in TOML format. The parents of its bodies are looked up among the bodies of the file
+/// and the `known` ones. `source` names the file in error messages.
+fn parse_system(text: &str, source: &str, known: &[CelestialBody]) -> Result<Vec<CelestialBody>, String> {
+    let file: SystemFile = toml::from_str(text).map_err(|error| format!("{source}: {error}"))?;
+    let mut bodies: Vec<CelestialBody> = Vec::new();
+    for (i, entry) in file.body.into_iter().enumerate() {
+        let name = entry.name.clone();
+        let body = entry.into_body(&bodies, known).map_err(|error| format!("{source}: body {} ({name}): {error}", i + 1))?;
+        if bodies.iter().any(|other| other.name == body.name) {
+            return Err(format!("{source}: body {} ({name}): there already is a body with this name", i + 1));
+        }
+        bodies.push(body);
+    }
+    Ok(bodies)
+}
+
+
+pub fn stock_bodies() -> Vec<CelestialBody> {
+    parse_system(STOCK_BODIES, "bodies/stock.toml", &[]).unwrap()
+}
+
+
+/// Loads additional bodies from a TOML file. Their parents may be any of the `known` bodies.
+pub fn load_system(path: &Path, known: &[CelestialBody]) -> Result<Vec<CelestialBody>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    parse_system(&text, &path.display().to_string(), known)
+}
+
+
+/// Adds `bodies` to the known ones. Bodies with the same name as an existing one replace it.
+pub fn add_bodies(known: &mut Vec<CelestialBody>, bodies: Vec<CelestialBody>) {
+    for body in bodies {
+        match known.iter_mut().find(|other| other.name == body.name) {
+            Some(other) => *other = body,
+            None => known.push(body),
+        }
+    }
+}
+
+
+/// Looks up a body by its name, ignoring the case
+pub fn find_body(bodies: &[CelestialBody], name: &str) -> Result<CelestialBody, String> {
+    bodies.iter().find(|body| body.name.eq_ignore_ascii_case(name)).cloned().ok_or_else(|| {
+        let names: Vec<&str> = bodies.iter().map(|body| body.name.as_str()).collect();
+        format!("there is no body named {name}, the bodies are {}", names.join(", "))
+    })
+}
@@ -0,0 +1,373 @@
+use crate::parts::{Part, Stage, part_mass_dry, is_radial_stage, stage_crossfeeds};
+use crate::resources::{FlowMode, Resource, ResourceAmounts, resource_mass};
+
+
+/// Largest change of the fuel fraction of a booster with a thrust curve during one phase. The curve is evaluated in
+/// the middle of every step, which keeps the burnout time accurate when the thrust is not constant.
+const CURVE_STEP: f32 = 0.05;
+
+
+/// Current resource contents of every part of the rocket, indexed by stage and part
+#[derive(Clone)]
+pub struct Tanks {
+    pub amounts: Vec<Vec<ResourceAmounts>>,
+}
+
+
+/// Time span of a burn during which the same set of engines is firing
+pub struct BurnPhase {
+    pub start: f32,
+    pub end: f32,
+    /// Stage and part indices of the firing engines, and their throttle setting. For boosters with a thrust curve,
+    /// this includes the multiplier of the curve.
+    pub engines: Vec<(usize, usize, f32)>,
+    /// Mass of the attached stages at `start`
+    pub start_mass: f32,
+    /// Propellant mass flow of all firing engines in t/s
+    pub mass_flow: f32,
+}
+
+
+/// Burn of one or more stages that fire together, up to the next separation
+pub struct Burn {
+    pub phases: Vec<BurnPhase>,
+    /// Stages that are jettisoned when the burn ends
+    pub dropped: Vec<usize>,
+    /// Drain timelines of all pools that were used during the burn
+    pub pools: Vec<PoolTimeline>,
+}
+
+
+/// Contents of a resource pool over the course of a burn
+pub struct PoolTimeline {
+    pub resource: Resource,
+    pub stage: usize,
+    /// Set for pools of resources that do not flow out of their part
+    pub part: Option<usize>,
+    /// Amount in units at the start of every phase and at the end of the burn, as (time since ignition, amount)
+    pub amounts: Vec<(f32, f32)>,
+}
+
+
+/// Resource that is available to an engine, as a set of (stage, part) slots of the `Tanks`
+struct Pool {
+    resource: Resource,
+    stage: usize,
+    /// Set for pools of resources that do not flow out of their part
+    part: Option<usize>,
+    slots: Vec<(usize, usize)>,
+    amount: f32,
+}
+
+
+/// Propellant of an engine, with the pools it can be drawn from in order of priority
+struct Feed {
+    resource: Resource,
+    flow: f32,
+    pools: Vec<usize>,
+}
+
+
+impl Tanks {
+    pub fn new(stages: &[Stage]) -> Tanks {
+        let amounts = stages.iter()
+            .map(|stage| stage.iter().map(|part| part.resource_amounts()).collect())
+            .collect();
+        Tanks { amounts }
+    }
+
+    /// Resources stored in the given stages
+    pub fn contents(&self, stages: &[usize]) -> ResourceAmounts {
+        let mut total = ResourceAmounts::default();
+        for amounts in stages.iter().flat_map(|&s| &self.amounts[s]) {
+            for (resource, amount) in amounts.iter() {
+                total[resource] += amount;
+            }
+        }
+        total
+    }
+
+    /// Current wet mass of stage `s`
+    pub fn stage_mass(&self, stages: &[Stage], s: usize) -> f32 {
+        part_mass_dry(&stages[s]) + resource_mass(&self.contents(&[s]))
+    }
+}
+
+
+impl BurnPhase {
+    /// Total mass at time `t`
+    pub fn mass(&self, t: f32) -> f32 {
+        self.start_mass - self.mass_flow * (t - self.start)
+    }
+
+    /// Thrust of the firing engines in kN at `pressure` atm
+    pub fn thrust(&self, stages: &[Stage], pressure: f32) -> f32 {
+        self.engines.iter().map(|&(s, i, throttle)| stages[s][i].thrust(pressure) * throttle).sum()
+    }
+}
+
+
+impl Feed {
+    /// Pool that is currently drained, if any is left
+    fn active_pool(&self, pools: &[Pool]) -> Option<usize> {
+        self.pools.iter().copied().find(|&p| pools[p].amount > 1e-6)
+    }
+}
+
+
+fn find_pool(pools: &[Pool], resource: Resource, stage: usize, part: Option<usize>) -> Option<usize> {
+    pools.iter().position(|pool| pool.resource == resource && pool.stage == stage && pool.part == part)
+}
+
+
+/// Collects the resources of the `attached` stages into pools, according to their flow mode
+fn make_pools(stages: &[Stage], attached: &[usize], tanks: &Tanks) -> Vec<Pool> {
+    let mut pools: Vec<Pool> = Vec::new();
+    for &s in attached {
+        for (i, amounts) in tanks.amounts[s].iter().enumerate() {
+            for (resource, _) in &stages[s][i].resources {
+                let amount = amounts[*resource];
+                let part = match resource.flow_mode() {
+                    FlowMode::NoFlow => Some(i),
+                    FlowMode::Stack | FlowMode::Vessel | FlowMode::Atmosphere => None,
+                };
+                match find_pool(&pools, *resource, s, part) {
+                    Some(p) => {
+                        pools[p].slots.push((s, i));
+                        pools[p].amount += amount;
+                    },
+                    None => pools.push(Pool { resource: *resource, stage: s, part, slots: vec![(s, i)], amount }),
+                }
+            }
+        }
+    }
+    pools
+}
+
+
+/// Stages whose stack resources are available to `stage`, in the order in which they are drained. Radial stages
+/// that crossfeed pass on their fuel to the stage they are attached to, which means that in an asparagus
+/// arrangement the outermost stage is drained first.
+fn fuel_sources(stages: &[Stage], stage: usize, attached: &[usize]) -> Vec<usize> {
+    let position = attached.iter().position(|&s| s == stage).unwrap();
+    let mut sources: Vec<usize> = attached[..position].iter().rev()
+        .copied()
+        .take_while(|&s| stage_crossfeeds(&stages[s]))
+        .collect();
+    sources.reverse();
+    sources.push(stage);
+    sources
+}
+
+
+/// Feeds of the propellants that are drawn from the tanks. The air of jet engines comes from the intakes instead, and
+/// limits their thrust during the flight.
+fn make_feeds(stages: &[Stage], stage: usize, engine: usize, attached: &[usize], pools: &[Pool]) -> Vec<Feed> {
+    let part = &stages[stage][engine];
+    let propellants = part.variant.propellants().iter().filter(|(resource, _)| resource.flow_mode() != FlowMode::Atmosphere);
+    propellants.map(|(resource, _)| {
+        let candidates: Vec<usize> = match resource.flow_mode() {
+            FlowMode::NoFlow => find_pool(pools, *resource, stage, Some(engine)).into_iter().collect(),
+            FlowMode::Stack => fuel_sources(stages, stage, attached).into_iter()
+                .filter_map(|s| find_pool(pools, *resource, s, None))
+                .collect(),
+            FlowMode::Vessel => attached.iter().filter_map(|&s| find_pool(pools, *resource, s, None)).collect(),
+            FlowMode::Atmosphere => Vec::new(),
+        };
+        Feed { resource: *resource, flow: part.resource_flow(*resource), pools: candidates }
+    }).collect()
+}
+
+
+/// Production of every resource in units/s by the generators of the `attached` stages
+fn resource_supply(stages: &[Stage], attached: &[usize]) -> ResourceAmounts {
+    let mut supply = ResourceAmounts::default();
+    for part in attached.iter().flat_map(|&s| &stages[s]) {
+        for (resource, rate) in part.variant.outputs() {
+            supply[*resource] += rate * part.copies();
+        }
+    }
+    supply
+}
+
+
+/// Throttle setting that an engine can sustain. An engine runs at full throttle while all of its propellants are
+/// available. Once a propellant pool is empty, it can only keep firing as far as generators supply that
+/// propellant, shared with all other engines that need it.
+fn engine_throttle(feeds: &[Feed], pools: &[Pool], supply: &ResourceAmounts, demand: &ResourceAmounts) -> f32 {
+    feeds.iter().map(|feed| {
+        if feed.active_pool(pools).is_some() {
+            1.0
+        } else {
+            let r = feed.resource;
+            if demand[r] > 0.0 { (supply[r] / demand[r]).min(1.0) } else { 0.0 }
+        }
+    }).fold(1.0, f32::min)
+}
+
+
+/// Fuel flow multiplier of a booster's thrust curve for the next step, the pool holding the booster's own fuel, and
+/// the amount that is left in the pool at the end of the step. Boosters without a curve run at a constant flow.
+fn curve_step(part: &Part, feeds: &[Feed], pools: &[Pool]) -> Option<(f32, usize, f32)> {
+    let curve = part.variant.thrust_curve()?;
+    let feed = feeds.iter().find(|feed| feed.resource.flow_mode() == FlowMode::NoFlow)?;
+    let pool = feed.active_pool(pools)?;
+    let capacity = part.resource_amounts()[feed.resource];
+    let fraction = pools[pool].amount / capacity;
+    let end = (fraction - CURVE_STEP).max(0.0);
+    Some((curve.evaluate((fraction + end) / 2.0).max(0.0), pool, end * capacity))
+}
+
+
+/// Removes the samples in the middle of stretches where the amount stays constant, as well as those after the pool
+/// has stopped changing
+fn simplify_timeline(timeline: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let changes = |a: usize, b: usize| (timeline[a].1 - timeline[b].1).abs() > 1e-3;
+    (0..timeline.len())
+        .filter(|&i| i == 0 || changes(i - 1, i) || (i + 1 < timeline.len() && changes(i, i + 1)))
+        .map(|i| timeline[i])
+        .collect()
+}
+
+
+/// Fires the engines of the `firing` stages, which must be a subset of the `attached` stages (ordered from the
+/// bottom up), until the next separation. Radially attached stages are dropped as soon as their engines burn out,
+/// while the others keep firing. Once no engine is left, all firing stages are dropped together.
+/// 
+/// The burn is split into phases, ending every time a propellant pool runs dry. Engines stop once one of their
+/// propellants is exhausted, and never ignite if they do not have access to all of them. The consumed resources
+/// are removed from `tanks`. Burns that are cut short after `until` s drop no stages.
+///
+/// With `jets` set, only the jet engines fire, and no stages are dropped. Their burn is planned at full throttle, and
+/// ends after `until` s. The time of the phases is the time that the jets would need at full throttle, which the
+/// flight dynamics stretch by the multipliers of their curves.
+pub fn plan_burn(stages: &[Stage], attached: &[usize], firing: &[usize], jets: bool, until: f32, tanks: &mut Tanks) -> Burn {
+    let mut pools = make_pools(stages, attached, tanks);
+    let initial_amounts: Vec<f32> = pools.iter().map(|pool| pool.amount).collect();
+    let supply = resource_supply(stages, attached);
+
+    let mut engines: Vec<(usize, usize, Vec<Feed>)> = Vec::new();
+    for &s in firing {
+        for (i, part) in stages[s].iter().enumerate() {
+            let feeds = make_feeds(stages, s, i, attached, &pools);
+            if part.variant.mass_flow() > 1e-6 && !feeds.is_empty() && part.variant.is_jet() == jets {
+                engines.push((s, i, feeds));
+            }
+        }
+    }
+
+    let mut phases = Vec::new();
+    let mut timelines: Vec<Vec<(f32, f32)>> = vec![Vec::new(); pools.len()];
+    let mut t = 0.0;
+    let mut mass: f32 = attached.iter().map(|&s| tanks.stage_mass(stages, s)).sum();
+    // Steps along a thrust curve are not sampled, only the phases where a pool runs dry
+    let mut sample = true;
+    let dropped = loop {
+        if sample {
+            for (timeline, pool) in timelines.iter_mut().zip(&pools) {
+                timeline.push((t, pool.amount));
+            }
+        }
+
+        let mut demand = ResourceAmounts::default();
+        for feed in engines.iter().flat_map(|(_, _, feeds)| feeds) {
+            demand[feed.resource] += feed.flow;
+        }
+        // Engines that flame out never reignite, since the pools do not refill
+        let steps: Vec<Option<(f32, usize, f32)>> = engines.iter()
+            .map(|(s, i, feeds)| curve_step(&stages[*s][*i], feeds, &pools))
+            .collect();
+        let throttles: Vec<f32> = engines.iter().zip(&steps)
+            .map(|((_, _, feeds), step)| {
+                engine_throttle(feeds, &pools, &supply, &demand) * step.map_or(1.0, |(multiplier, _, _)| multiplier)
+            })
+            .collect();
+        let mut kept = throttles.iter();
+        engines.retain(|_| *kept.next().unwrap() > 1e-6);
+        let steps: Vec<Option<(f32, usize, f32)>> = steps.into_iter().zip(&throttles)
+            .filter(|(_, &throttle)| throttle > 1e-6)
+            .map(|(step, _)| step)
+            .collect();
+        let throttles: Vec<f32> = throttles.into_iter().filter(|&throttle| throttle > 1e-6).collect();
+
+        // Radial stages are dropped once they have neither engines nor fuel left to contribute
+        let burned_out: Vec<usize> = firing.iter().copied()
+            .filter(|&s| is_radial_stage(&stages[s]) && !engines.iter().any(|(es, _, _)| *es == s))
+            .filter(|&s| !stage_crossfeeds(&stages[s]) || !pools.iter().any(
+                |pool| pool.stage == s && pool.resource.flow_mode() == FlowMode::Stack && pool.amount > 1e-6
+            ))
+            .collect();
+        if jets {
+            if engines.is_empty() || until - t <= 1e-4 {
+                break Vec::new();
+            }
+        } else if !burned_out.is_empty() {
+            break burned_out;
+        } else if engines.is_empty() {
+            break firing.to_vec();
+        } else if until - t <= 1e-4 {
+            break Vec::new();
+        }
+
+        let mut drain = vec![0.0; pools.len()];
+        let mut mass_flow = 0.0;
+        for ((_, _, feeds), throttle) in engines.iter().zip(&throttles) {
+            for feed in feeds {
+                if let Some(pool) = feed.active_pool(&pools) {
+                    drain[pool] += feed.flow * throttle;
+                }
+                mass_flow += feed.flow * throttle * feed.resource.density();
+            }
+        }
+        // Generated resources are used up first, before drawing from the pools
+        let mut remaining_supply = supply.clone();
+        for (pool, rate) in pools.iter().zip(drain.iter_mut()) {
+            let available = &mut remaining_supply[pool.resource];
+            let used = available.min(*rate);
+            *rate -= used;
+            *available -= used;
+        }
+
+        let duration = pools.iter().zip(&drain)
+            .filter(|(_, &rate)| rate > 0.0)
+            .map(|(pool, rate)| pool.amount / rate)
+            .fold(f32::INFINITY, f32::min);
+        let duration = duration.min(until - t);
+        if duration.is_infinite() {
+            // Nothing limits the burn, which only happens for engines that are fully powered by generators
+            break if jets { Vec::new() } else { firing.to_vec() };
+        }
+        let step_duration = steps.iter().flatten()
+            .filter(|(_, pool, _)| drain[*pool] > 0.0)
+            .map(|(_, pool, end)| (pools[*pool].amount - end) / drain[*pool])
+            .fold(f32::INFINITY, f32::min);
+        sample = step_duration >= duration;
+        let duration = duration.min(step_duration);
+        let firing_engines = engines.iter().zip(&throttles).map(|((s, i, _), throttle)| (*s, *i, *throttle)).collect();
+        phases.push(BurnPhase { start: t, end: t + duration, engines: firing_engines, start_mass: mass, mass_flow });
+
+        for (pool, rate) in pools.iter_mut().zip(&drain) {
+            pool.amount = (pool.amount - rate * duration).max(0.0);
+        }
+        t += duration;
+        mass -= mass_flow * duration;
+    };
+
+    // Write the remaining amounts back into the parts of each pool
+    for (pool, initial) in pools.iter().zip(initial_amounts) {
+        let fraction = if initial > 0.0 { pool.amount / initial } else { 0.0 };
+        for &(s, i) in &pool.slots {
+            tanks.amounts[s][i][pool.resource] *= fraction;
+        }
+    }
+    let pools = pools.iter().zip(timelines)
+        .filter(|(pool, timeline)| timeline.iter().any(|(_, amount)| (amount - pool.amount).abs() > 1e-3))
+        .map(|(pool, timeline)| PoolTimeline {
+            resource: pool.resource,
+            stage: pool.stage,
+            part: pool.part,
+            amounts: simplify_timeline(&timeline),
+        })
+        .collect();
+    Burn { phases, dropped, pools }
+}
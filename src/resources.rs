@@ -0,0 +1,196 @@
+use std::ops::{Index, IndexMut};
+use std::sync::{LazyLock, RwLock};
+
+
+/// How a resource can be moved between the parts of a vessel
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlowMode {
+    /// Can only be used by the part that stores it
+    NoFlow,
+    /// Shared between all parts of the same stage
+    Stack,
+    /// Shared across the whole vessel, drawing from the stage that is dropped next first
+    Vessel,
+    /// Taken in from the air by intakes during the flight, and never stored
+    Atmosphere,
+}
+use FlowMode::*;
+
+
+/// Handle of a resource in the registry. The stock resources are always registered, and catalogues can add more. The
+/// handle carries the properties that the simulation needs, so that it never has to look them up in the registry.
+#[derive(Debug, Copy, Clone)]
+pub struct Resource {
+    index: usize,
+    /// Mass of one unit in t
+    density: f32,
+    flow_mode: FlowMode,
+}
+
+
+#[derive(Debug)]
+struct ResourceInfo {
+    resource: Resource,
+    /// Name as used by KSP
+    name: String,
+}
+
+
+/// Resources that are always known. Their handles are their positions in this list.
+const STOCK_RESOURCES: &[(&str, f32, FlowMode)] = &[
+    ("LiquidFuel", 0.005, Stack),
+    ("Oxidizer", 0.005, Stack),
+    ("SolidFuel", 0.0075, NoFlow),
+    ("MonoPropellant", 0.004, Vessel),
+    ("XenonGas", 0.0001, Vessel),
+    ("ElectricCharge", 0.0, Vessel),
+    ("IntakeAir", 0.005, Atmosphere),
+];
+
+
+/// Registry of all known resources, indexed by the handles. It is only needed to look up names.
+static REGISTRY: LazyLock<RwLock<Vec<ResourceInfo>>> = LazyLock::new(|| {
+    let stock = STOCK_RESOURCES.iter().enumerate()
+        .map(|(index, &(name, ..))| ResourceInfo { resource: Resource::stock(index), name: name.to_string() })
+        .collect();
+    RwLock::new(stock)
+});
+
+
+/// Amounts of resources in units, in the order of the registry. Resources that are not listed are not present.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceAmounts(Vec<(Resource, f32)>);
+
+
+/// List of resources and their amounts in units. Depending on the context, this is either the contents of a
+/// part, or the mixture ratio of the propellants that an engine burns.
+pub type Resources = Vec<(Resource, f32)>;
+
+
+impl Resource {
+    /// Stock resources that the simulation treats specially
+    pub const SOLID_FUEL: Resource = Resource::stock(2);
+    pub const INTAKE_AIR: Resource = Resource::stock(6);
+
+    const fn stock(index: usize) -> Resource {
+        let (_, density, flow_mode) = STOCK_RESOURCES[index];
+        Resource { index, density, flow_mode }
+    }
+
+    pub fn name(&self) -> String {
+        REGISTRY.read().unwrap()[self.index].name.clone()
+    }
+
+    pub fn from_name(name: &str) -> Option<Resource> {
+        REGISTRY.read().unwrap().iter().find(|info| info.name == name).map(|info| info.resource)
+    }
+
+    /// Mass of one unit in t
+    pub fn density(&self) -> f32 {
+        self.density
+    }
+
+    pub fn flow_mode(&self) -> FlowMode {
+        self.flow_mode
+    }
+}
+
+
+impl PartialEq for Resource {
+    fn eq(&self, other: &Resource) -> bool {
+        self.index == other.index
+    }
+}
+
+impl Eq for Resource {}
+
+
+impl ResourceAmounts {
+    pub fn iter(&self) -> impl Iterator<Item = (Resource, f32)> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn scaled(mut self, factor: f32) -> ResourceAmounts {
+        for (_, amount) in &mut self.0 {
+            *amount *= factor;
+        }
+        self
+    }
+}
+
+
+impl Index<Resource> for ResourceAmounts {
+    type Output = f32;
+
+    fn index(&self, resource: Resource) -> &f32 {
+        self.0.iter().find(|(r, _)| *r == resource).map_or(&0.0, |(_, amount)| amount)
+    }
+}
+
+
+impl IndexMut<Resource> for ResourceAmounts {
+    fn index_mut(&mut self, resource: Resource) -> &mut f32 {
+        let position = match self.0.binary_search_by_key(&resource.index, |(r, _)| r.index) {
+            Ok(position) => position,
+            Err(position) => {
+                self.0.insert(position, (resource, 0.0));
+                position
+            }
+        };
+        &mut self.0[position].1
+    }
+}
+
+
+/// Adds a resource to the registry, and returns its handle. Resources that are already known keep their definition,
+/// so that the stock resources cannot be changed under the parts that use them.
+pub fn register_resource(name: &str, density: f32, flow_mode: FlowMode) -> Result<Resource, String> {
+    let mut registry = REGISTRY.write().unwrap();
+    if let Some(info) = registry.iter().find(|info| info.name == name) {
+        return Ok(info.resource);
+    }
+    if name.trim().is_empty() {
+        return Err("the name of the resource is empty".to_string());
+    }
+    if !(density.is_finite() && density >= 0.0) {
+        return Err(format!("the density of {name} must be a positive number, not {density}"));
+    }
+    let resource = Resource { index: registry.len(), density, flow_mode };
+    registry.push(ResourceInfo { resource, name: name.to_string() });
+    Ok(resource)
+}
+
+
+pub fn resource_amounts(resources: &[(Resource, f32)]) -> ResourceAmounts {
+    let mut amounts = ResourceAmounts::default();
+    for (resource, amount) in resources {
+        amounts[*resource] += amount;
+    }
+    amounts
+}
+
+
+/// Mass of the resources in t
+pub fn resource_mass(amounts: &ResourceAmounts) -> f32 {
+    amounts.0.iter().map(|(resource, amount)| resource.density * amount).sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_any_number_of_resources() {
+        let resources: Vec<Resource> = (0..20)
+            .map(|i| register_resource(&format!("TestResource{i}"), 0.001 * i as f32, Vessel).unwrap())
+            .collect();
+        assert_eq!(resources[19].name(), "TestResource19");
+        assert_eq!(Resource::from_name("TestResource7"), Some(resources[7]));
+
+        let amounts = resource_amounts(&[(resources[19], 10.0), (Resource::SOLID_FUEL, 100.0), (resources[19], 5.0)]);
+        assert_eq!(amounts[resources[19]], 15.0);
+        assert_eq!(amounts[resources[3]], 0.0);
+        assert!((resource_mass(&amounts) - (15.0 * 0.019 + 100.0 * 0.0075)).abs() < 1e-5);
+    }
+}
@@ -1,22 +1,230 @@
-use crate::parts::{SOLID_FUEL_DENSITY, EFF_FUEL_DENSITY};
-use crate::parts::{rocket_stages, part_mass_wet, part_fuel_mass, part_mass_dry};
-use crate::parts::{Part, Stage};
-use crate::parts::PartVariant::*;
+use std::mem;
+use std::sync::LazyLock;
+
+use crate::parts::{part_mass_wet, part_mass_dry, part_drag_area, part_intake_area, part_wing_area, is_radial_stage};
+use crate::parts::Stage;
+use crate::vessel::{rocket_stages, Vessel};
+use crate::propulsion::{plan_burn, BurnPhase, PoolTimeline, Tanks};
+use crate::resources::{Resource, ResourceAmounts};
 use crate::vector::Vector;
 use crate::integrator;
-use crate::kerbin;
+use crate::celestial_body::CelestialBody;
+use crate::orbit::Orbit;
+use crate::float_curve::FloatCurve;
+
+
+/// Standard gravity, used for converting between specific impulse and mass flow
+pub const G0: f32 = 9.81;
+
+/// Latitude in degrees and height above the surface in m of the launch pad of the KSC
+pub const KSC_LATITUDE: f32 = -0.0972;
+pub const KSC_ALTITUDE: f32 = 70.0;
+
+
+/// Multiplier of the drag coefficient over the Mach number, as FloatCurve keys. Drag rises steeply through the
+/// transonic region, peaks just above Mach 1 and falls off again at higher speeds.
+const MACH_DRAG_KEYS: &[[f32; 4]] = &[
+    [0.0, 1.0, 0.0, 0.0],
+    [0.85, 1.0, 0.0, 0.0],
+    [1.1, 2.0, 0.0, 0.0],
+    [2.0, 1.5, -0.3, -0.3],
+    [5.0, 1.2, 0.0, 0.0],
+];
+
+/// Lift coefficient of the wings over the angle of attack in degrees, as FloatCurve keys. The wings stall above 15
+/// degrees.
+const LIFT_KEYS: &[[f32; 4]] = &[
+    [0.0, 0.0, 0.09, 0.09],
+    [15.0, 1.2, 0.0, 0.0],
+    [30.0, 0.8, -0.02, -0.02],
+    [90.0, 0.0, 0.0, 0.0],
+];
+
+static MACH_DRAG: LazyLock<FloatCurve> = LazyLock::new(|| FloatCurve::from_keys(MACH_DRAG_KEYS).unwrap());
+static LIFT: LazyLock<FloatCurve> = LazyLock::new(|| FloatCurve::from_keys(LIFT_KEYS).unwrap());
+
+/// Drag coefficient of the wings per squared lift coefficient, 1 / (pi * aspect ratio * span efficiency) for the
+/// stubby wings of a spaceplane
+const INDUCED_DRAG: f32 = 0.1;
+
+/// Speeds above the rotation speed, as a fraction of it, over which planes pitch up
+const ROTATION_RANGE: f32 = 0.2;
+
+/// Intakes take in air as if they were flying this many m/s faster, so that jets can spool up on the runway
+const INTAKE_SPEED: f32 = 20.0;
+
+/// Interval in s at which a jet flight is checked for whether the jets are still of use
+const JET_STEP: f32 = 2.0;
 
+/// Jets are shut down once their thrust exceeds the drag by less than this fraction of the weight, since they can
+/// then barely accelerate the vessel anymore
+const MIN_JET_EXCESS_THRUST: f32 = 0.05;
 
-pub const GRAVITY: f32 = 9.81;
+/// Longest time in s that the jets are fired for, before the other engines are lit
+const MAX_JET_TIME: f32 = 1200.0;
+
+/// Engines whose Isp at the launch pressure falls below this fraction of their vacuum Isp are taken to stall
+const STALL_ISP_FRACTION: f32 = 0.05;
+
+
+/// Altitude based pitch program for the ascent. The vessel flies straight up until `start_altitude`, and then
+/// pitches over towards `end_pitch` (degrees above the horizon), which is reached at `end_altitude`.
+#[derive(Debug, Copy, Clone)]
+pub struct PitchProgram {
+    pub start_altitude: f32,
+    pub end_altitude: f32,
+    pub end_pitch: f32,
+    /// Shape of the turn. Values below 1.0 pitch over quickly at first and then flatten out.
+    pub exponent: f32,
+}
+
+
+/// Runway takeoff of a spaceplane. The plane accelerates with its nose level until it reaches `rotation_speed`, and
+/// then gradually pitches up to `climb_pitch` (degrees above the horizon). From there, the pitch falls with the altitude
+/// until it reaches the horizon at `end_altitude`.
+#[derive(Debug, Copy, Clone)]
+pub struct TakeoffProgram {
+    pub rotation_speed: f32,
+    pub climb_pitch: f32,
+    pub end_altitude: f32,
+}
+
+
+#[derive(Debug, Copy, Clone)]
+pub enum AscentMode {
+    /// Purely vertical flight, thrust is always pointed away from the planet
+    Vertical,
+    GravityTurn(PitchProgram),
+    HorizontalTakeoff(TakeoffProgram),
+}
+
+
+/// Position and velocity of the vessel in polar coordinates around the center of the launch body
+#[derive(Debug, Copy, Clone)]
+pub struct FlightState {
+    pub radius: f32,
+    pub angle: f32,
+    pub radial_velocity: f32,
+    pub tangential_velocity: f32,
+}
+
+
+/// Launch pad on a rotating body, and the inclination of the orbit that the vessel heads for. The flight itself is
+/// simulated in the plane of that orbit.
+#[derive(Debug, Clone)]
+pub struct LaunchSite {
+    pub body: CelestialBody,
+    /// Latitude in degrees, north of the equator
+    pub latitude: f32,
+    /// Height of the pad above the surface in m. The terrain is taken to be this high everywhere.
+    pub altitude: f32,
+    /// Inclination of the target orbit in degrees. Orbits above 90 degrees are retrograde.
+    pub inclination: f32,
+}
+
+
+/// Air around the vessel
+struct Air {
+    altitude: f32,
+    /// Pressure in atm
+    pressure: f32,
+    /// Density in kg/m^3
+    density: f32,
+    /// Velocity relative to the air, which turns with the body
+    radial_velocity: f32,
+    tangential_velocity: f32,
+    speed: f32,
+    mach: f32,
+    /// Whether jets can breathe the air
+    oxygen: bool,
+}
+
+
+/// Aerodynamic properties of the attached stages, as areas in m^2
+struct Aerodynamics {
+    /// Drag coefficient times the frontal area
+    drag_area: f32,
+    wing_area: f32,
+    intake_area: f32,
+}
+
+
+/// Mach numbers seen during a burn
+struct MachRecord {
+    peak: f32,
+    /// Time since launch at which Mach 1 was first crossed
+    transonic_time: Option<f32>,
+    /// Time since launch and Mach number of the last observation
+    last: (f32, f32),
+}
+
+
+/// Flight on jet engines at the start of a burn, before the other engines are lit
+struct JetFlight {
+    delta_v: f32,
+    drag_losses: f32,
+    state: FlightState,
+    /// Time in s that the jets fired for
+    duration: f32,
+    /// Thrust of the jets in kN when standing on the ground at sea level
+    launch_thrust: f32,
+    pools: Vec<PoolTimeline>,
+}
 
 
 pub struct StageInfo {
     pub wet_mass: f32,
     pub dry_mass: f32,
+    /// Thrust to weight ratio on the surface of the launch body
     pub twr: f32,
     pub delta_v: f32,
     pub burnout_altitude: f32,
     pub burnout_velocity: f32,
+    pub drag_losses: f32,
+    pub downrange_distance: f32,
+    /// Angle of the velocity vector above the local horizon in degrees
+    pub flight_path_angle: f32,
+    pub horizontal_velocity: f32,
+    /// Highest Mach number reached inside the atmosphere during the burn
+    pub peak_mach: f32,
+    /// Time since launch in s at which the vessel went through Mach 1, if that happened during the burn
+    pub transonic_time: Option<f32>,
+    /// Time since launch in s, altitude in m and speed in m/s at which the jets were shut down, if the vessel flew
+    /// on jets
+    pub jet_cutoff: Option<(f32, f32, f32)>,
+    /// Engines that are lit on the launch pad, but whose thrust collapses at the launch pressure
+    pub stalled_engines: Vec<String>,
+    /// Orbit the vessel would follow if it coasted from burnout
+    pub orbit: Orbit,
+    pub resources: Vec<ResourceUsage>,
+    /// How the resource pools were drained during the burn
+    pub pools: Vec<PoolTimeline>,
+}
+
+
+/// Amount of a resource in units that a stage carries at ignition, and that is consumed during its burn. The
+/// consumption includes resources that are drawn from other stages.
+pub struct ResourceUsage {
+    pub resource: Resource,
+    pub held: f32,
+    pub used: f32,
+}
+
+
+/// Coast from the end of the ascent up to the apoapsis, and the burn there that circularizes the orbit
+pub struct Circularization {
+    /// Stage whose burn ends the ascent
+    pub ascent_stage: usize,
+    /// Time in s from its burnout to the apoapsis
+    pub coast_time: f32,
+    /// Altitude in m of the apoapsis
+    pub altitude: f32,
+    /// Delta-v in m/s that it takes to circularize the orbit
+    pub delta_v: f32,
+    /// Stages that fire during the burn. The burn is cut short if they run out before it is done.
+    pub stages: Vec<usize>,
+    /// Orbit at the end of the burn
+    pub orbit: Orbit,
 }
 
 
@@ -27,6 +235,165 @@ pub struct RocketInfo {
     pub stage_info: Vec<StageInfo>,
     pub stages: Vec<Stage>,
     pub final_altitude: f32,
+    /// Velocity in m/s along the flight path that the rotation of the body gave the vessel at launch
+    pub rotation_bonus: f32,
+    /// Extra delta-v in m/s that it takes to reach the target inclination
+    pub inclination_cost: f32,
+    /// Nothing if the ascent never lifts the apoapsis out of the atmosphere
+    pub circularization: Option<Circularization>,
+    /// Orbit at the end of the circularization burn, or at burnout of the final stage without one
+    pub apoapsis: f32,
+    pub periapsis: f32,
+    pub eccentricity: f32,
+    pub reached_orbit: bool,
+    /// Delta-v of the stages that are still attached after the ascent, less the circularization burn and the
+    /// inclination cost
+    pub leftover_delta_v: f32,
+}
+
+
+impl Default for PitchProgram {
+    fn default() -> Self {
+        PitchProgram { start_altitude: 1000.0, end_altitude: 45000.0, end_pitch: 0.0, exponent: 0.5 }
+    }
+}
+
+
+impl PitchProgram {
+    /// Pitch above the horizon in radians
+    pub fn pitch(&self, altitude: f32) -> f32 {
+        let progress = ((altitude - self.start_altitude) / (self.end_altitude - self.start_altitude)).clamp(0.0, 1.0);
+        let pitch = 90.0 - (90.0 - self.end_pitch) * progress.powf(self.exponent);
+        pitch.to_radians()
+    }
+}
+
+
+impl Default for TakeoffProgram {
+    fn default() -> Self {
+        TakeoffProgram { rotation_speed: 100.0, climb_pitch: 20.0, end_altitude: 45000.0 }
+    }
+}
+
+
+impl TakeoffProgram {
+    /// Pitch above the horizon in radians
+    pub fn pitch(&self, altitude: f32, speed: f32) -> f32 {
+        // Rotating over a range of speeds, rather than all at once, keeps a plane that can barely take off from
+        // flipping between the two attitudes on the runway
+        let rotation = ((speed - self.rotation_speed) / (ROTATION_RANGE * self.rotation_speed)).clamp(0.0, 1.0);
+        let progress = (altitude / self.end_altitude).clamp(0.0, 1.0);
+        (self.climb_pitch * rotation * (1.0 - progress)).to_radians()
+    }
+}
+
+
+impl AscentMode {
+    /// Pitch above the horizon in radians
+    pub fn pitch(&self, altitude: f32, speed: f32) -> f32 {
+        match self {
+            AscentMode::Vertical => 90.0f32.to_radians(),
+            AscentMode::GravityTurn(program) => program.pitch(altitude),
+            AscentMode::HorizontalTakeoff(program) => program.pitch(altitude, speed),
+        }
+    }
+
+    /// Whether the ascent ends at the first burnout that lifts the apoapsis out of the atmosphere, with the stages
+    /// after it left for the circularization. Vertical ascents fly every stage straight up, like a sounding rocket.
+    fn circularizes(&self) -> bool {
+        !matches!(self, AscentMode::Vertical)
+    }
+
+    /// Angle in radians between the nose at `pitch` and the direction of travel
+    fn angle_of_attack(&self, pitch: f32, radial_velocity: f32, tangential_velocity: f32) -> f32 {
+        match self {
+            AscentMode::HorizontalTakeoff(_) => pitch - radial_velocity.atan2(tangential_velocity),
+            _ => 0.0,
+        }
+    }
+}
+
+
+impl LaunchSite {
+    /// Checks that the coordinates and the inclination are in range. The poles are left out, since no launch azimuth
+    /// can be worked out there.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.latitude.is_nan() || self.latitude.abs() >= 90.0 {
+            return Err(format!("the latitude must be between -90 and 90 degrees, not {}", self.latitude));
+        }
+        if !self.altitude.is_finite() {
+            return Err(format!("the altitude must be a number, not {}", self.altitude));
+        }
+        if !(0.0..=180.0).contains(&self.inclination) {
+            return Err(format!("the inclination must be between 0 and 180 degrees, not {}", self.inclination));
+        }
+        Ok(())
+    }
+
+    pub fn ground_radius(&self) -> f32 {
+        self.body.radius + self.altitude
+    }
+
+    /// Speed in m/s at which the rotation of the body carries the pad eastwards
+    fn surface_speed(&self) -> f32 {
+        self.body.rotation_rate * self.ground_radius() * self.latitude.to_radians().cos()
+    }
+
+    /// Sine of the launch azimuth, measured from north in a frame that does not rotate with the body. Inclinations
+    /// that cannot be reached from the latitude are replaced by the closest one, due east or due west.
+    fn azimuth_sine(&self) -> f32 {
+        (self.inclination.to_radians().cos() / self.latitude.to_radians().cos()).clamp(-1.0, 1.0)
+    }
+
+    /// Rate in rad/s at which the air turns along the flight path
+    fn air_rotation_rate(&self) -> f32 {
+        self.body.rotation_rate * self.latitude.to_radians().cos() * self.azimuth_sine()
+    }
+
+    /// Velocity in m/s along the flight path that the vessel has on the pad. Westward launches start out with a
+    /// negative bonus.
+    pub fn rotation_bonus(&self) -> f32 {
+        self.surface_speed() * self.azimuth_sine()
+    }
+
+    /// Extra delta-v in m/s for reaching the target inclination with an orbital speed of `orbital_speed` m/s. Unless
+    /// the vessel launches due east, the rotation of the body carries it sideways, which has to be cancelled. Orbits
+    /// that cannot be reached from the latitude need a plane change on top of that.
+    pub fn inclination_cost(&self, orbital_speed: f32) -> f32 {
+        let (sine, surface_speed) = (self.azimuth_sine(), self.surface_speed());
+        let along = orbital_speed - surface_speed * sine;
+        let sideways = (along * along + surface_speed * surface_speed * (1.0 - sine * sine)).sqrt() - along;
+        let reachable = (self.latitude.to_radians().cos() * sine).clamp(-1.0, 1.0).acos();
+        let plane_change = 2.0 * orbital_speed * ((self.inclination.to_radians() - reachable).abs() / 2.0).sin();
+        sideways + plane_change
+    }
+}
+
+
+impl FlightState {
+    /// Vessel standing on the launch pad, which moves with the rotation of the body
+    pub fn launch(site: &LaunchSite) -> FlightState {
+        FlightState { radius: site.ground_radius(), angle: 0.0, radial_velocity: 0.0, tangential_velocity: site.rotation_bonus() }
+    }
+
+    pub fn altitude(&self, body: &CelestialBody) -> f32 {
+        self.radius - body.radius
+    }
+
+    pub fn speed(&self) -> f32 {
+        (self.radial_velocity * self.radial_velocity + self.tangential_velocity * self.tangential_velocity).sqrt()
+    }
+
+    /// Distance travelled over the surface by `time` s after launch. The angle is measured in a frame that does not
+    /// rotate, so the ground that turned along with the body is taken off.
+    pub fn downrange_distance(&self, site: &LaunchSite, time: f32) -> f32 {
+        (self.angle - site.air_rotation_rate() * time) * site.body.radius
+    }
+
+    /// Angle of the velocity vector above the local horizon in degrees
+    pub fn flight_path_angle(&self) -> f32 {
+        self.radial_velocity.atan2(self.tangential_velocity).to_degrees()
+    }
 }
 
 
@@ -47,7 +414,33 @@ pub fn print_stage_summary(stage_info: &StageInfo, header: &str) {
     println!(" THRUST TO WEIGHT: {:.2}", stage_info.twr);
     println!(" BURNOUT ALTITUDE: {}km", (stage_info.burnout_altitude / 1000.0) as i32);
     println!(" BURNOUT VELOCITY: {}m/s", stage_info.burnout_velocity as i32);
-    println!("");
+    println!("      DRAG LOSSES: {}m/s", stage_info.drag_losses as i32);
+    println!("        DOWNRANGE: {}km", (stage_info.downrange_distance / 1000.0) as i32);
+    println!("FLIGHT PATH ANGLE: {:.1}deg", stage_info.flight_path_angle);
+    println!(" HORIZONTAL VELO.: {}m/s", stage_info.horizontal_velocity as i32);
+    println!("        PEAK MACH: {:.2}", stage_info.peak_mach);
+    if let Some(time) = stage_info.transonic_time {
+        println!("  MACH 1 CROSSING: T+{}s", time as i32);
+    }
+    if let Some((time, altitude, speed)) = stage_info.jet_cutoff {
+        println!("       JET CUTOFF: T+{}s, {}km, {}m/s", time as i32, (altitude / 1000.0) as i32, speed as i32);
+    }
+    if !stage_info.stalled_engines.is_empty() {
+        println!("  STALLED ENGINES: {}", stage_info.stalled_engines.join(", "));
+    }
+    for usage in &stage_info.resources {
+        println!("{:>17}: {:.1} held, {:.1} used", usage.resource.name().to_uppercase(), usage.held, usage.used);
+    }
+    for pool in &stage_info.pools {
+        let owner = match pool.part {
+            Some(part) => format!("{}.{}", pool.stage, part),
+            None => pool.stage.to_string(),
+        };
+        let label = format!("POOL {} {}", owner, pool.resource.name().to_uppercase());
+        let timeline: Vec<String> = pool.amounts.iter().map(|(t, amount)| format!("{amount:.1}@{t:.0}s")).collect();
+        println!("{:>17}: {}", label, timeline.join(" -> "));
+    }
+    println!();
 }
 
 
@@ -56,146 +449,501 @@ pub fn print_rocket_summary(rocket_info: &RocketInfo) {
     println!("      LAUNCH MASS: {:.2}t", rocket_info.launch_mass);
     println!("          DELTA-V: {}m/s", rocket_info.delta_v as i32);
     println!("       PART COUNT: {}", rocket_info.part_count);
-    println!("");
+    println!("   ROTATION BONUS: {}m/s", rocket_info.rotation_bonus as i32);
+    println!(" INCLINATION COST: {}m/s", rocket_info.inclination_cost as i32);
+    if let Some(circularization) = &rocket_info.circularization {
+        let stages: Vec<String> = circularization.stages.iter().map(|s| s.to_string()).collect();
+        println!("     ASCENT STAGE: {}", circularization.ascent_stage);
+        println!("       COAST TIME: {}s", circularization.coast_time as i32);
+        println!("  CIRCULARIZATION: {}m/s at {}km", circularization.delta_v as i32, (circularization.altitude / 1000.0) as i32);
+        println!("     CIRC. STAGES: {}", if stages.is_empty() { "NONE".to_string() } else { stages.join(", ") });
+    }
+    if rocket_info.apoapsis.is_finite() {
+        println!("         APOAPSIS: {}km", (rocket_info.apoapsis / 1000.0) as i64);
+    } else {
+        println!("         APOAPSIS: ESCAPE");
+    }
+    println!("        PERIAPSIS: {}km", (rocket_info.periapsis / 1000.0) as i64);
+    println!("     ECCENTRICITY: {:.3}", rocket_info.eccentricity);
+    println!("    REACHED ORBIT: {}", if rocket_info.reached_orbit { "YES" } else { "NO" });
+    println!(" LEFTOVER DELTA-V: {}m/s", rocket_info.leftover_delta_v as i32);
+    println!();
 }
 
 
-fn get_burnout_times(stage: &[Part]) -> Vec<f32> {
-    let mut burnout_times = Vec::new();
-    let fuel_mass = part_fuel_mass(stage) * EFF_FUEL_DENSITY;
-    let mut liquid_mass_flow: f32 = 0.0;
-    for part in stage {
-        if let SolidBooster{ fuel, thrust_asl, isp_asl, ..} = part.variant {
-            let solid_fuel = fuel * SOLID_FUEL_DENSITY;
-            let solid_mass_flow = thrust_asl / (isp_asl * GRAVITY);
-            if solid_fuel > 1e-6 && solid_mass_flow > 1e-6 {
-                burnout_times.push(solid_fuel / solid_mass_flow);
-            }
+fn mach_number(body: &CelestialBody, altitude: f32, speed: f32) -> f32 {
+    if altitude < body.atmosphere_height() {
+        speed / body.speed_of_sound(altitude)
+    } else {
+        0.0
+    }
+}
+
+
+impl Air {
+    /// Air around a vessel at a distance `radius` from the center of the body, flying at the given velocity
+    fn at(site: &LaunchSite, radius: f32, radial_velocity: f32, tangential_velocity: f32) -> Air {
+        let body = &site.body;
+        let altitude = radius - body.radius;
+        let tangential_velocity = tangential_velocity - site.air_rotation_rate() * radius;
+        let speed = (radial_velocity * radial_velocity + tangential_velocity * tangential_velocity).sqrt();
+        Air {
+            altitude,
+            pressure: body.pressure(altitude),
+            density: body.density(altitude),
+            radial_velocity,
+            tangential_velocity,
+            speed,
+            mach: mach_number(body, altitude, speed),
+            oxygen: body.has_oxygen(altitude),
         }
-        if let Engine{ thrust_asl, isp_asl, .. } = part.variant {
-            liquid_mass_flow += thrust_asl / (isp_asl * GRAVITY);
+    }
+}
+
+
+impl Aerodynamics {
+    /// The stacked stages form a single stack, and the radial stages sit beside it
+    fn new(stages: &[Stage], attached: &[usize]) -> Aerodynamics {
+        let (radial, stacked): (Vec<usize>, Vec<usize>) = attached.iter().partition(|&&s| is_radial_stage(&stages[s]));
+        Aerodynamics {
+            drag_area: part_drag_area(stacked.iter().flat_map(|&s| &stages[s]))
+                + radial.iter().map(|&s| part_drag_area(&stages[s])).sum::<f32>(),
+            wing_area: attached.iter().map(|&s| part_wing_area(&stages[s])).sum(),
+            intake_area: attached.iter().map(|&s| part_intake_area(&stages[s])).sum(),
         }
     }
-    if liquid_mass_flow > 1e-6 && fuel_mass > 1e-6 {
-        burnout_times.push(fuel_mass / liquid_mass_flow);
+
+    /// Drag and lift in kN at an angle of attack in radians. The drag coefficient grows around the speed of sound,
+    /// and the wings add drag of their own as they produce lift.
+    fn forces(&self, air: &Air, angle_of_attack: f32) -> (f32, f32) {
+        let dynamic_pressure = 0.5 * air.density * air.speed * air.speed;
+        let lift_coefficient = if self.wing_area > 0.0 {
+            LIFT.evaluate(angle_of_attack.to_degrees().abs()) * angle_of_attack.signum()
+        } else {
+            0.0
+        };
+        let drag_area = self.drag_area * MACH_DRAG.evaluate(air.mach)
+            + self.wing_area * INDUCED_DRAG * lift_coefficient * lift_coefficient;
+        (dynamic_pressure * drag_area / 1000.0, dynamic_pressure * self.wing_area * lift_coefficient / 1000.0)
     }
-    
-    burnout_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    burnout_times
 }
 
 
-fn flight_dynamics(t: f32, state: &Vector<3>, stage: &[Part], payload_mass: f32) -> Vector<3> {
-    // The state consists of delta-velocity, velocity and height
-    let [_, v, altitude] = state.data;
-
-    let fuel_mass = part_fuel_mass(stage) * EFF_FUEL_DENSITY;
+impl MachRecord {
+    fn new(time: f32, air: &Air) -> MachRecord {
+        MachRecord { peak: air.mach, transonic_time: None, last: (time, air.mach) }
+    }
 
-    let mut liquid_thrust_asl: f32 = 0.0;
-    let mut liquid_thrust_vac: f32 = 0.0;
-    let mut liquid_mass_flow: f32 = 0.0;
-    let mut solid_rockets: Vec<(f32, f32, f32, f32)> = Vec::new();
-    for part in stage {
-        if let SolidBooster{ fuel, thrust_asl, thrust_vac, isp_asl, ..} = part.variant {
-            solid_rockets.push((fuel * SOLID_FUEL_DENSITY, thrust_asl, thrust_vac, thrust_asl / (isp_asl * GRAVITY)));
+    /// Takes note of the Mach number at `time` since launch. The crossing of Mach 1 is interpolated between the
+    /// observations.
+    fn observe(&mut self, time: f32, air: &Air) {
+        let (last_time, last_mach) = self.last;
+        if self.transonic_time.is_none() && last_mach < 1.0 && air.mach >= 1.0 {
+            self.transonic_time = Some(last_time + (time - last_time) * (1.0 - last_mach) / (air.mach - last_mach));
         }
-        if let Engine{ thrust_asl,  thrust_vac, isp_asl, .. } = part.variant {
-            liquid_thrust_asl += thrust_asl;
-            liquid_thrust_vac += thrust_vac;
-            liquid_mass_flow += thrust_asl / (isp_asl * GRAVITY)
+        self.peak = self.peak.max(air.mach);
+        self.last = (time, air.mach);
+    }
+}
+
+
+fn flight_dynamics(state: &Vector<6>, air: &Air, thrust: f32, mass: f32, aero: &Aerodynamics, ascent: &AscentMode, site: &LaunchSite) -> Vector<6> {
+    // The state consists of delta-velocity, velocity lost to drag, and the polar position (radius, angle)
+    // and velocity (radial, tangential) of the vessel.
+    let [_, _, r, _, vr, vt] = state.data;
+    let (air_vr, air_vt, speed) = (air.radial_velocity, air.tangential_velocity, air.speed);
+    let pitch = ascent.pitch(air.altitude, speed);
+
+    // Drag acts against the direction of travel through the air, and lift at a right angle to it. Rockets fly their
+    // gravity turn close to prograde, so only planes fly with their nose above the flight path and get lift from
+    // their wings.
+    let (drag, lift) = aero.forces(air, ascent.angle_of_attack(pitch, air_vr, air_vt));
+
+    let a = thrust / mass;
+    let a_drag = drag / mass;
+    let a_lift = lift / mass;
+    let (drag_r, drag_t) = if speed > 1e-6 { (a_drag * air_vr / speed, a_drag * air_vt / speed) } else { (0.0, 0.0) };
+    let (lift_r, lift_t) = if speed > 1e-6 { (a_lift * air_vt / speed, -a_lift * air_vr / speed) } else { (0.0, 0.0) };
+    let a_r = a * pitch.sin() - drag_r + lift_r - site.body.gravity(r) + vt * vt / r;
+    let a_t = a * pitch.cos() - drag_t + lift_t - vr * vt / r;
+    if air.altitude <= site.altitude && (vr < 0.0 || (vr == 0.0 && a_r <= 0.0)) {
+        // Resting on the ground, which holds the vessel until it produces enough thrust or lift to lift off. Planes
+        // roll along the runway until then. Vessels that fall back down stay where they hit the ground, which keeps
+        // turning with the body.
+        if vr == 0.0 && (air_vt > 0.0 || a_t > 0.0) {
+            return Vector{data: [a, a_drag, 0.0, vt / r, 0.0, a_t]};
         }
+        return Vector{data: [a, a_drag, 0.0, site.air_rotation_rate(), 0.0, 0.0]};
     }
+    Vector{data: [a, a_drag, vr, vt / r, a_r, a_t]}
+}
+
 
-    let atmo_p = kerbin::get_pressure(altitude);
+#[allow(clippy::too_many_arguments)]
+fn integrate_dv(stages: &[Stage], phases: &[BurnPhase], aero: &Aerodynamics, ascent: &AscentMode, site: &LaunchSite, state: FlightState, start: f32, mach: &mut MachRecord) -> (f32, f32, FlightState) {
+    let mut y = Vector{ data: [0.0, 0.0, state.radius, state.angle, state.radial_velocity, state.tangential_velocity] };
+    for phase in phases {
+        // Thrust curves of solid boosters are followed by splitting the burn into short phases, with the multiplier
+        // of the curve in the throttle of the engine
+        let f = |t, state: Vector<6>| {
+            let air = Air::at(site, state[2], state[4], state[5]);
+            flight_dynamics(&state, &air, phase.thrust(stages, air.pressure), phase.mass(t), aero, ascent, site)
+        };
+        let observe = |t: f32, state: &Vector<6>| mach.observe(start + t, &Air::at(site, state[2], state[4], state[5]));
+        let (res, _) = integrator::rk45(
+            f,
+            y,
+            phase.start, phase.end,
+            Vector{ data: [1e-3, 1e-3, 1e-9, 1e-6, 1e-6, 1e-6]},
+            1e-4,
+            observe,
+        );
+        y = res;
+    }
+    let [delta_v, drag_losses, radius, angle, radial_velocity, tangential_velocity] = y.data;
+
+    (delta_v, drag_losses, FlightState { radius, angle, radial_velocity, tangential_velocity })
+}
+
+
+/// Thrust in kN of the jets that fire during `phase`, and their fuel flow relative to full throttle. All jets are
+/// throttled back alike if the intakes cannot supply enough air for them.
+fn jet_thrust(stages: &[Stage], phase: &BurnPhase, air: &Air, intake_area: f32) -> (f32, f32) {
     let mut thrust = 0.0;
-    let mut mass = payload_mass + part_mass_wet(stage);
-    if fuel_mass > liquid_mass_flow * t && liquid_thrust_asl > 1e-6 {
-        thrust += liquid_thrust_asl * atmo_p + liquid_thrust_vac * (1.0 - atmo_p);
+    let mut mass_flow = 0.0;
+    let mut air_demand = 0.0;
+    for &(s, i, throttle) in &phase.engines {
+        let part = &stages[s][i];
+        let multiplier = part.variant.air_multiplier(air.mach, air.density) * throttle;
+        thrust += part.thrust(air.pressure) * multiplier;
+        mass_flow += part.mass_flow() * multiplier;
+        air_demand += part.resource_flow(Resource::INTAKE_AIR) * multiplier;
     }
-    mass -= fuel_mass.min(liquid_mass_flow * t);
-    
-    for (s_fuel_mass, s_thrust, s_thrust_vac, s_mass_flow) in &mut solid_rockets {
-        if *s_fuel_mass > *s_mass_flow * t && *s_thrust > 1e-6 {
-            thrust += *s_thrust * atmo_p + *s_thrust_vac * (1.0 - atmo_p);
+    let air_supply = if air.oxygen {
+        intake_area * air.density * (air.speed + INTAKE_SPEED) / (1000.0 * Resource::INTAKE_AIR.density())
+    } else {
+        0.0
+    };
+    let air_limit = if air_demand > 0.0 { (air_supply / air_demand).min(1.0) } else { 1.0 };
+    let throttle = if phase.mass_flow > 0.0 { mass_flow / phase.mass_flow } else { 0.0 };
+    (thrust * air_limit, throttle * air_limit)
+}
+
+
+/// Converts a time at full throttle into the flight time, between samples of (time at full throttle, flight time)
+fn flight_time(samples: &[(f32, f32)], full_throttle_time: f32) -> f32 {
+    match samples.iter().position(|&(sample, _)| sample >= full_throttle_time) {
+        Some(0) => samples[0].1,
+        Some(i) => {
+            let ((a, time_a), (b, time_b)) = (samples[i - 1], samples[i]);
+            time_a + (time_b - time_a) * (full_throttle_time - a) / (b - a)
         }
-        mass -= s_fuel_mass.min(*s_mass_flow * t);
+        None => samples[samples.len() - 1].1,
     }
-
-    let a = thrust / mass;
-    let a_real = a - GRAVITY;
-    Vector{data: [a, a_real, v]}
 }
 
 
-fn integrate_dv(stage: &[Part], payload_mass: f32, altitude: f32, velocity: f32) -> (f32, f32, f32, f32) {
-    let f = |t, state| flight_dynamics(t, &state, stage, payload_mass);
+/// Fires the jets of the `firing` stages for as long as they can accelerate the vessel, and removes the propellant
+/// that they burned from `tanks`. Returns nothing if the stages have no jets, or if they cannot get the vessel moving.
+#[allow(clippy::too_many_arguments)]
+fn fly_on_jets(stages: &[Stage], attached: &[usize], firing: &[usize], tanks: &mut Tanks, aero: &Aerodynamics, ascent: &AscentMode, site: &LaunchSite, state: FlightState, start: f32, mach: &mut MachRecord) -> Option<JetFlight> {
+    // The burn at full throttle tells how the propellant runs out. The jets work their way through it at the pace of
+    // their throttle.
+    let plan = plan_burn(stages, attached, firing, true, f32::INFINITY, &mut tanks.clone());
+    let last = plan.phases.last()?;
+    let dry_mass = last.mass(last.end);
+    let phase_at = |full_throttle_time: f32| plan.phases.iter().find(|phase| full_throttle_time < phase.end);
+    let launch_air = Air::at(site, site.ground_radius(), 0.0, site.rotation_bonus());
+    let (launch_thrust, _) = jet_thrust(stages, &plan.phases[0], &launch_air, aero.intake_area);
 
-    let mut times = get_burnout_times(stage);
-    times.insert(0, 0.0);
+    // Besides the usual state, the time at full throttle that the jets would have needed for the burned propellant
+    let f = |_t, y: Vector<7>| {
+        let [delta_v, drag_losses, r, angle, vr, vt, full_throttle_time] = y.data;
+        let air = Air::at(site, r, vr, vt);
+        let (thrust, throttle, mass) = match phase_at(full_throttle_time) {
+            Some(phase) => {
+                let (thrust, throttle) = jet_thrust(stages, phase, &air, aero.intake_area);
+                (thrust, throttle, phase.mass(full_throttle_time))
+            }
+            None => (0.0, 0.0, dry_mass),
+        };
+        let state = Vector{data: [delta_v, drag_losses, r, angle, vr, vt]};
+        let [a, a_drag, dr, dangle, a_r, a_t] = flight_dynamics(&state, &air, thrust, mass, aero, ascent, site).data;
+        Vector{data: [a, a_drag, dr, dangle, a_r, a_t, throttle]}
+    };
 
-    let mut delta_v = 0.0;
-    let mut velocity = velocity;
-    let mut altitude = altitude;
+    let mut y = Vector{ data: [0.0, 0.0, state.radius, state.angle, state.radial_velocity, state.tangential_velocity, 0.0] };
+    let mut t = 0.0;
+    let mut samples = vec![(0.0, 0.0)];
+    while t < MAX_JET_TIME {
+        let [_, _, r, _, vr, vt, full_throttle_time] = y.data;
+        let air = Air::at(site, r, vr, vt);
+        let Some(phase) = phase_at(full_throttle_time) else {
+            break;
+        };
+        let (thrust, _) = jet_thrust(stages, phase, &air, aero.intake_area);
+        let pitch = ascent.pitch(air.altitude, air.speed);
+        let (drag, _) = aero.forces(&air, ascent.angle_of_attack(pitch, air.radial_velocity, air.tangential_velocity));
+        let weight = phase.mass(full_throttle_time) * site.body.gravity(r);
+        let [.., a_r, a_t, _] = f(t, y).data;
+        let stuck = air.speed < 1e-3 && a_r == 0.0 && a_t == 0.0;
+        let crashed = air.altitude <= site.altitude && vr < 0.0;
+        if thrust - drag < MIN_JET_EXCESS_THRUST * weight || stuck || crashed {
+            break;
+        }
 
-    for t_i in 0..times.len()-1 {
+        let observe = |t: f32, y: &Vector<7>| mach.observe(start + t, &Air::at(site, y[2], y[4], y[5]));
         let (res, _) = integrator::rk45(
-            &f, 
-            Vector{ data: [delta_v, velocity, altitude] },
-            times[t_i]+1e-6, times[t_i+1]-1e-3,
-            Vector{ data: [1e-3, 1e-9, 1e-9]},
-            1e-4
+            f,
+            y,
+            t, t + JET_STEP,
+            Vector{ data: [1e-3, 1e-3, 1e-9, 1e-6, 1e-6, 1e-6, 1e-3]},
+            1e-4,
+            observe,
         );
-        delta_v  = res[0];
-        velocity = res[1];
-        altitude = res[2];
+        y = res;
+        t += JET_STEP;
+        samples.push((y[6], t));
+    }
+    if t == 0.0 {
+        return None;
     }
 
-    // Get thrust information for TWR ratio calculation. This assumes nothing has burned out in the stage.
-    let mut thrust = 0.0;
-    for part in stage {
-        thrust += match part.variant {
-            SolidBooster{ thrust_asl, .. } => thrust_asl,
-            Engine{ thrust_asl, .. } => thrust_asl,
-            _ => 0.0
+    let [delta_v, drag_losses, radius, angle, radial_velocity, tangential_velocity, full_throttle_time] = y.data;
+    let burn = plan_burn(stages, attached, firing, true, full_throttle_time, tanks);
+    let pools = burn.pools.into_iter()
+        .map(|pool| PoolTimeline {
+            amounts: pool.amounts.iter().map(|&(time, amount)| (flight_time(&samples, time), amount)).collect(),
+            ..pool
+        })
+        .collect();
+    Some(JetFlight {
+        delta_v,
+        drag_losses,
+        state: FlightState { radius, angle, radial_velocity, tangential_velocity },
+        duration: t,
+        launch_thrust,
+        pools,
+    })
+}
+
+
+/// Appends the timelines of the `later` pools, whose times start at `offset` s, to those of `pools`
+fn merge_pools(pools: &mut Vec<PoolTimeline>, later: Vec<PoolTimeline>, offset: f32) {
+    for mut timeline in later {
+        for sample in &mut timeline.amounts {
+            sample.0 += offset;
+        }
+        let same_pool = |pool: &&mut PoolTimeline| {
+            pool.resource == timeline.resource && pool.stage == timeline.stage && pool.part == timeline.part
+        };
+        match pools.iter_mut().find(same_pool) {
+            Some(pool) => pool.amounts.extend(timeline.amounts.into_iter().skip(1)),
+            None => pools.push(timeline),
+        }
+    }
+}
+
+
+/// Delta-v in m/s of a burn in vacuum
+fn vacuum_delta_v(stages: &[Stage], phases: &[BurnPhase]) -> f32 {
+    phases.iter()
+        .filter(|phase| phase.mass_flow > 0.0)
+        .map(|phase| phase.thrust(stages, 0.0) / phase.mass_flow * (phase.mass(phase.start) / phase.mass(phase.end)).ln())
+        .sum()
+}
+
+
+/// Time in s after ignition at which a burn in vacuum has given `delta_v` m/s, from the rocket equation. Burns that
+/// cannot give that much run until they end.
+fn vacuum_burn_time(stages: &[Stage], phases: &[BurnPhase], delta_v: f32) -> f32 {
+    let mut remaining = delta_v;
+    for phase in phases.iter().filter(|phase| phase.mass_flow > 0.0) {
+        let exhaust_velocity = phase.thrust(stages, 0.0) / phase.mass_flow;
+        let start_mass = phase.mass(phase.start);
+        let phase_delta_v = exhaust_velocity * (start_mass / phase.mass(phase.end)).ln();
+        if remaining < phase_delta_v {
+            let end_mass = start_mass / (remaining / exhaust_velocity).exp();
+            return phase.start + (start_mass - end_mass) / phase.mass_flow;
         }
+        remaining -= phase_delta_v;
     }
+    phases.last().map_or(0.0, |phase| phase.end)
+}
 
-    (delta_v, thrust, altitude, velocity)
+
+/// True if the burnout in `state` lifts the apoapsis out of the atmosphere, unless the vessel is already falling back
+/// by then
+fn ends_ascent(state: &FlightState, orbit: &Orbit, body: &CelestialBody) -> bool {
+    orbit.apoapsis.is_finite() && orbit.apoapsis > body.atmosphere_height() &&
+        (state.radial_velocity >= 0.0 || orbit.is_stable(body))
 }
 
-pub fn analyze_stages(stages: &Vec<Vec<Part>>) -> Vec<StageInfo> {
+
+/// Flies the rocket from one separation to the next. Every entry of the result corresponds to one such burn, and
+/// describes the parts that are jettisoned at its end, as well as the propellant burned during it. Jets fire first,
+/// and the other engines are lit once the jets can barely accelerate the vessel anymore.
+///
+/// Unless the ascent is vertical, the flight is only simulated up to the burnout that ends the ascent. The vessel then
+/// coasts up to the apoapsis on a Keplerian orbit, where the remaining stages circularize the orbit with impulsive
+/// burns. These stages report the delta-v of a full burn in vacuum, but only fire for as long as the circularization
+/// needs them.
+pub fn analyze_stages(stages: &[Stage], site: &LaunchSite, ascent: &AscentMode) -> (Vec<StageInfo>, Option<Circularization>) {
+    let body = &site.body;
     let mut stage_info = Vec::new();
-    let mut alt = 0.0;
-    let mut vel = 0.0;
-    for (i, stage) in stages.iter().enumerate() {
-        let mut payload_mass = 0.0;
-        for j in (i+1)..stages.len() {
-            payload_mass += part_mass_wet(&stages[j])
-        }
-        let rocket_mass = payload_mass +  part_mass_wet(stage);
-        let (deltav, thrust, a, v) = integrate_dv(&stage, payload_mass, alt, vel);
-        alt = a;
-        vel = v;
+    let mut circularization: Option<Circularization> = None;
+    // Delta-v in m/s that the circularization still needs
+    let mut needed = 0.0;
+    let mut state = FlightState::launch(site);
+    let mut time = 0.0;
+    let mut tanks = Tanks::new(stages);
+    let mut attached: Vec<usize> = (0..stages.len()).collect();
+    while !attached.is_empty() {
+        // Radial stages fire together with the stage they are attached to
+        let core = attached.iter().position(|&s| !is_radial_stage(&stages[s])).unwrap_or(attached.len() - 1);
+        let firing = &attached[..=core];
+
+        let aero = Aerodynamics::new(stages, &attached);
+        let rocket_mass: f32 = attached.iter().map(|&s| tanks.stage_mass(stages, s)).sum();
+        let before = tanks.contents(&attached);
+        let stage_contents: Vec<ResourceAmounts> = (0..stages.len()).map(|s| tanks.contents(&[s])).collect();
+
+        let ignition = time;
+        let mut mach = MachRecord::new(time, &Air::at(site, state.radius, state.radial_velocity, state.tangential_velocity));
+        let mut pools = Vec::new();
+        let (mut delta_v, mut drag_losses, mut jet_thrust) = (0.0, 0.0, 0.0);
+        let mut jet_cutoff = None;
+        // Jets cannot breathe above the atmosphere, so they sit out the circularization
+        if circularization.is_none() {
+            let jets = fly_on_jets(stages, &attached, firing, &mut tanks, &aero, ascent, site, state, time, &mut mach);
+            if let Some(jets) = jets {
+                state = jets.state;
+                time += jets.duration;
+                pools = jets.pools;
+                (delta_v, drag_losses, jet_thrust) = (jets.delta_v, jets.drag_losses, jets.launch_thrust);
+                jet_cutoff = Some((time, state.altitude(body), state.speed()));
+            }
+        }
+        let burn_start = time;
+
+        // In orbit, the stages only burn as much as the circularization needs. Their report still describes the whole
+        // stage, which is planned as if it burned out.
+        let (mut burn, planned, orbit) = match &mut circularization {
+            None => {
+                let burn = plan_burn(stages, &attached, firing, false, f32::INFINITY, &mut tanks);
+                let (burn_delta_v, burn_drag_losses, burnout_state) = integrate_dv(stages, &burn.phases, &aero, ascent, site, state, time, &mut mach);
+                state = burnout_state;
+                delta_v += burn_delta_v;
+                drag_losses += burn_drag_losses;
+                (burn, None, Orbit::from_state(&state, body))
+            }
+            Some(circularization) => {
+                let planned = plan_burn(stages, &attached, firing, false, f32::INFINITY, &mut tanks.clone());
+                delta_v = vacuum_delta_v(stages, &planned.phases);
+                let fired = delta_v.min(needed);
+                if fired > 0.0 {
+                    circularization.stages.push(stage_info.len());
+                    circularization.orbit = circularization.orbit.burn_at_apoapsis(fired, body);
+                    state.tangential_velocity += fired;
+                    needed -= fired;
+                }
+                let until = vacuum_burn_time(stages, &planned.phases, fired);
+                let burn = plan_burn(stages, &attached, firing, false, until, &mut tanks);
+                (burn, Some(planned), circularization.orbit)
+            }
+        };
+        time += burn.phases.last().map_or(0.0, |phase| phase.end);
+        merge_pools(&mut pools, mem::take(&mut burn.pools), burn_start - ignition);
+        let planned = planned.as_ref().unwrap_or(&burn);
+
+        // Get thrust information for TWR ratio calculation. This assumes nothing has burned out in the stage.
+        let launch_pressure = body.pressure(site.altitude);
+        let surface_gravity = body.gravity(site.ground_radius());
+        let thrust = jet_thrust + planned.phases.first().map_or(0.0, |phase| phase.thrust(stages, launch_pressure));
+        // Vacuum engines get choked by a thick atmosphere, like the one of Eve
+        let stalled_engines = match planned.phases.first() {
+            Some(phase) if burn_start == 0.0 => phase.engines.iter()
+                .map(|&(s, i, _)| &stages[s][i])
+                .filter(|part| part.variant.isp(launch_pressure) < STALL_ISP_FRACTION * part.variant.isp(0.0))
+                .map(|part| part.name.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let after = tanks.contents(&attached);
+        let resources = before.iter()
+            .map(|(resource, amount)| ResourceUsage {
+                resource,
+                held: planned.dropped.iter().map(|&s| stage_contents[s][resource]).sum(),
+                used: amount - after[resource],
+            })
+            .filter(|usage| usage.held > 0.0 || usage.used > 0.0)
+            .collect();
+
+        attached.retain(|s| !planned.dropped.contains(s));
+        let remaining_mass: f32 = attached.iter().map(|&s| tanks.stage_mass(stages, s)).sum();
         stage_info.push(StageInfo{
-            wet_mass: part_mass_wet(stage),
-            dry_mass: part_mass_dry(stage),
-            delta_v: deltav,
-            twr: thrust / (GRAVITY * rocket_mass),
-            burnout_altitude: alt,
-            burnout_velocity: vel,
+            wet_mass: rocket_mass - remaining_mass,
+            dry_mass: planned.dropped.iter().map(|&s| part_mass_dry(&stages[s])).sum(),
+            delta_v,
+            twr: thrust / (surface_gravity * rocket_mass),
+            burnout_altitude: state.altitude(body),
+            burnout_velocity: state.speed(),
+            drag_losses,
+            downrange_distance: state.downrange_distance(site, time),
+            flight_path_angle: state.flight_path_angle(),
+            horizontal_velocity: state.tangential_velocity,
+            peak_mach: mach.peak,
+            transonic_time: mach.transonic_time,
+            jet_cutoff,
+            stalled_engines,
+            orbit,
+            resources,
+            pools,
         });
+
+        if circularization.is_none() && ascent.circularizes() && ends_ascent(&state, &orbit, body) {
+            let coast_time = orbit.time_to_apoapsis(state.radius, state.radial_velocity, body);
+            needed = orbit.circularization_delta_v(body).max(0.0);
+            circularization = Some(Circularization {
+                ascent_stage: stage_info.len() - 1,
+                coast_time,
+                altitude: orbit.apoapsis,
+                delta_v: needed,
+                stages: Vec::new(),
+                orbit,
+            });
+            state = orbit.coast_to_apoapsis(&state, body);
+            time += coast_time;
+        }
     }
-    stage_info
+    (stage_info, circularization)
 }
 
 
-pub fn analyze_rocket(rocket: &Vec<Part>) -> RocketInfo {
-    let stages = rocket_stages(rocket);
-    let stage_info = analyze_stages(&stages);
-    let launch_mass = part_mass_wet(&rocket);
+/// Flies the vessel from the launch site and sums up how it performed
+pub fn analyze_rocket(vessel: &Vessel, site: &LaunchSite, ascent: &AscentMode) -> RocketInfo {
+    let body = &site.body;
+    let stages = rocket_stages(vessel);
+    let (stage_info, circularization) = analyze_stages(&stages, site, ascent);
+    let launch_mass = part_mass_wet(&vessel.parts);
     let delta_v=  stage_info.iter().map(|s| s.delta_v).sum();
-    let part_count = rocket.len();
+    let part_count = vessel.parts.iter().map(|part| part.symmetry as usize).sum();
     let final_altitude = stage_info.last().unwrap().burnout_altitude;
+    let orbit = circularization.as_ref().map_or(stage_info.last().unwrap().orbit, |circularization| circularization.orbit);
+    let reached_orbit = orbit.is_stable(body);
+
+    // The inclination is corrected at the speed of a circular orbit at the final altitude
+    let orbit_altitude = circularization.as_ref().map_or(final_altitude, |circularization| circularization.altitude);
+    let orbital_speed = (body.gm / (body.radius + orbit_altitude).max(site.ground_radius())).sqrt();
+    let inclination_cost = site.inclination_cost(orbital_speed);
+    let leftover_delta_v = match (&circularization, reached_orbit) {
+        (Some(circularization), true) => {
+            let remaining: f32 = stage_info[circularization.ascent_stage + 1..].iter().map(|s| s.delta_v).sum();
+            (remaining - circularization.delta_v - inclination_cost).max(0.0)
+        }
+        _ => 0.0,
+    };
 
     RocketInfo {
         launch_mass,
@@ -204,5 +952,13 @@ pub fn analyze_rocket(rocket: &Vec<Part>) -> RocketInfo {
         stage_info,
         stages,
         final_altitude,
+        rotation_bonus: site.rotation_bonus(),
+        inclination_cost,
+        circularization,
+        apoapsis: orbit.apoapsis,
+        periapsis: orbit.periapsis,
+        eccentricity: orbit.eccentricity,
+        reached_orbit,
+        leftover_delta_v,
     }
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+use crate::parts::{Part, SizeClass, Stage, is_radial_stage};
+use crate::parts::PartVariant::*;
+
+
+/// Node of the parent part that a part is attached to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AttachNode {
+    /// Stacked below the parent part
+    Stack,
+    /// Mounted on the side of the parent part
+    Radial,
+}
+
+
+/// A rocket as a tree of parts, where every part except the root is attached to a node of its parent
+#[derive(Debug, Clone, Default)]
+pub struct Vessel {
+    /// The root part comes first, and every part comes after its parent
+    pub parts: Vec<Part>,
+    /// Parent part and the node on it for every part. The root part has no parent.
+    pub parents: Vec<Option<(usize, AttachNode)>>,
+}
+
+
+impl Vessel {
+    /// Attaches `part` to `parent`, or makes it the root part if there is no parent. Returns the index of the part.
+    pub fn attach(&mut self, part: Part, parent: Option<(usize, AttachNode)>) -> usize {
+        assert!(parent.is_some() != self.parts.is_empty(), "a vessel has exactly one root part");
+        self.parts.push(part);
+        self.parents.push(parent);
+        self.parts.len() - 1
+    }
+
+    /// Indices of the parts attached to part `i`
+    pub fn children(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        self.parents.iter().enumerate()
+            .filter(move |(_, parent)| parent.is_some_and(|(p, _)| p == i))
+            .map(|(child, _)| child)
+    }
+
+    /// Describes every stack join where the nodes of the two parts do not match in size, or where the number of parts
+    /// does not match the number of nodes of a multi-coupler
+    pub fn stack_errors(&self) -> Vec<String> {
+        let node_name = |node: Option<SizeClass>| node.map_or("no node".to_string(), |size| size.to_string());
+        let mut errors = Vec::new();
+        for (part, parent) in self.parts.iter().zip(&self.parents) {
+            let Some((parent, AttachNode::Stack)) = *parent else {
+                continue;
+            };
+            let parent = &self.parts[parent];
+            if part.top_node.is_none() || part.top_node != parent.bottom_node {
+                errors.push(format!("{} ({}) cannot be stacked below {} ({})",
+                    part, node_name(part.top_node), parent, node_name(parent.bottom_node)));
+            } else if part.symmetry != parent.symmetry * parent.bottom_nodes() {
+                errors.push(format!("{} does not fill the {} nodes below {}",
+                    part, parent.symmetry * parent.bottom_nodes(), parent));
+            }
+        }
+        errors
+    }
+
+    /// Adds the stages that start at part `root` to `stages`, in the order that they are fired. Stacked stages come
+    /// first, followed by the radial stages that fire together with the stage itself.
+    fn collect_stages(&self, root: usize, stages: &mut Vec<Vec<usize>>) {
+        let mut stage_parts = Vec::new();
+        let mut separated = Vec::new();
+        let mut open = vec![root];
+        while let Some(i) = open.pop() {
+            stage_parts.push(i);
+            for child in self.children(i) {
+                if let Decoupler | RadialDecoupler{..} = self.parts[child].variant {
+                    separated.push(child);
+                } else {
+                    open.push(child);
+                }
+            }
+        }
+        separated.sort_by_key(|&i| matches!(self.parts[i].variant, RadialDecoupler{..}));
+        for s in separated {
+            self.collect_stages(s, stages);
+        }
+        // Parts are listed from the bottom up, so that the decoupler of a stage comes last
+        stage_parts.reverse();
+        stages.push(stage_parts);
+    }
+
+    /// Indices of the parts in every stage, in the same order as `rocket_stages`
+    pub fn stage_indices(&self) -> Vec<Vec<usize>> {
+        let mut stages = Vec::new();
+        if self.parts.is_empty() {
+            stages.push(Vec::new());
+        } else {
+            self.collect_stages(0, &mut stages);
+        }
+        stages
+    }
+}
+
+
+/// Builds the tree from the list form, where the parts are listed from the bottom of the rocket to the top. Every
+/// part is stacked below the one after it, except for stages ending in a radial decoupler, which are mounted on
+/// the side of the stage above them. Surface mounted parts are put on the side of the part above them.
+impl From<&[Part]> for Vessel {
+    fn from(parts: &[Part]) -> Vessel {
+        let mut vessel = Vessel::default();
+        let mut bottom = None;
+        let mut side = None;
+        for group in list_stages(parts).iter().rev() {
+            let mut parent = if is_radial_stage(group) {
+                side.or(bottom).map(|p| (p, AttachNode::Radial))
+            } else {
+                bottom.map(|p| (p, AttachNode::Stack))
+            };
+            side = None;
+            for part in group.iter().rev() {
+                if part.is_surface_mounted() && !matches!(part.variant, RadialDecoupler{..}) {
+                    if let Some((p, _)) = parent {
+                        vessel.attach(part.clone(), Some((p, AttachNode::Radial)));
+                        continue;
+                    }
+                }
+                let i = vessel.attach(part.clone(), parent);
+                let node = if let RadialDecoupler{..} = part.variant { AttachNode::Radial } else { AttachNode::Stack };
+                parent = Some((i, node));
+                bottom = Some(i);
+                // Radial stages attach to the topmost structural part of the stage above them
+                if side.is_none() && !part.is_surface_mounted() && !matches!(part.variant, Decoupler) {
+                    side = Some(i);
+                }
+            }
+        }
+        vessel
+    }
+}
+
+
+fn list_stages(parts: &[Part]) -> Vec<Stage> {
+    // Splits a rocket into stages, as separated by decouplers
+    // Decouplers are included in the lower stage (because they are jettisoned with them)
+    // A stage that ends in a radial decoupler is strapped to the side of the stage above it
+    let mut stages = Vec::new();
+    let mut stage_parts = Vec::<Part>::new();
+    for part in parts {
+        stage_parts.push(part.clone());
+        if let Decoupler | RadialDecoupler{..} = part.variant {
+            stages.push(stage_parts);
+            stage_parts = Vec::new();
+        }
+    }
+    stages.push(stage_parts);
+    stages
+}
+
+
+/// Splits the vessel into stages at its decouplers, in the order that they are fired. Decouplers are included in the
+/// lower stage, because they are jettisoned with it.
+pub fn rocket_stages(vessel: &Vessel) -> Vec<Stage> {
+    vessel.stage_indices().iter()
+        .map(|stage| stage.iter().map(|&i| vessel.parts[i].clone()).collect())
+        .collect()
+}